@@ -1,20 +1,349 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::effects::EffectKind;
 
 #[derive(Component)]
 pub struct Ball{
     pub direction: Vec2,
+    /// Speed before power-up effects. Creeps up the longer the ball is in play.
+    pub speed: f32,
+    /// Where the ball was before this frame's movement, or its last contact
+    /// point. Collisions are swept from here to the current position.
+    pub previous: Vec2,
 }
 
 #[derive(Component)]
 pub struct Brick{
+    pub kind: BrickKind,
     pub hp: u8,
+    pub max_hp: u8,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BrickKind {
+    #[default]
+    Normal,
+    /// Never breaks; does not count towards clearing the level.
+    Steel,
+    /// Tough and worth more points.
+    Gold,
+    /// Hidden until the first hit.
+    Invisible,
+    /// Damages the bricks around it when destroyed.
+    Explosive,
+    /// Rebuilds itself unless its whole link group is destroyed in quick
+    /// succession.
+    Regenerating,
+}
+
+impl BrickKind {
+    pub fn is_destructible(self) -> bool {
+        self != BrickKind::Steel
+    }
+
+    pub fn score(self) -> u32 {
+        match self {
+            BrickKind::Normal => 10,
+            BrickKind::Steel => 0,
+            BrickKind::Gold => 50,
+            BrickKind::Invisible => 25,
+            BrickKind::Explosive => 15,
+            BrickKind::Regenerating => 10,
+        }
+    }
+
+    /// Texture used when the level does not override it.
+    pub fn default_sprite(self, hp: u8) -> &'static str {
+        match self {
+            BrickKind::Steel => "sprites/tileGrey_11.png",
+            BrickKind::Gold => "sprites/bricks/gold.png",
+            BrickKind::Invisible => "sprites/bricks/glass.png",
+            BrickKind::Explosive => "sprites/bricks/explosive.png",
+            BrickKind::Regenerating => "sprites/bricks/regenerating.png",
+            BrickKind::Normal if hp >= 2 => "sprites/element_yellow_rectangle.png",
+            BrickKind::Normal => "sprites/element_green_rectangle.png",
+        }
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            BrickKind::Gold => Color::rgb(1.0, 0.75, 0.2),
+            BrickKind::Explosive => Color::rgb(1.0, 0.35, 0.2),
+            BrickKind::Regenerating => Color::rgb(0.4, 0.9, 1.0),
+            _ => Color::WHITE,
+        }
+    }
+
+    /// Impact clip and how to play it. Pitch and volume tell apart the kinds
+    /// that share a clip.
+    pub fn hit_sound(self) -> (&'static str, PlaybackSettings) {
+        const MEDIUM: &str = "audio/impactPunch_medium_004.ogg";
+        const HEAVY: &str = "audio/impactPunch_heavy_001.ogg";
+        let once = PlaybackSettings::ONCE;
+        match self {
+            BrickKind::Normal => (MEDIUM, once),
+            BrickKind::Steel => (HEAVY, once),
+            BrickKind::Gold => (MEDIUM, once.with_speed(1.6)),
+            BrickKind::Invisible => (MEDIUM, once.with_speed(1.25).with_volume(0.5)),
+            BrickKind::Explosive => (HEAVY, once.with_speed(0.7)),
+            BrickKind::Regenerating => (HEAVY, once.with_speed(1.4).with_volume(0.7)),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Paddle{
+    /// Current size, used for the sprite, collisions and confinement.
+    pub size: Vec2,
+}
+
+#[derive(Component)]
+pub struct PowerUp {
+    pub kind: PowerUpKind,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PowerUpKind {
+    /// One more ball from the paddle.
+    MultiBall,
+    /// Every ball in play splits into three.
+    SplitBall,
+    BonusPoints,
+    SlowBall,
+    ExpandPaddle,
+    /// Curse.
+    ShrinkPaddle,
+    Laser,
+    Catch,
+    Fireball,
+    /// Barrier along the bottom edge that bounces balls back a few times.
+    SafetyNet,
+    FastBall,
+    Magnet,
+    /// Curse: left and right are swapped for a while.
+    ReverseControls,
+    /// Curse: every ball gets a lot faster, for good.
+    SpeedUp,
+    /// Curse: all balls but one disappear.
+    SingleBall,
+}
+
+impl PowerUpKind {
+    /// Harmful pickups the player should dodge.
+    pub fn is_curse(self) -> bool {
+        matches!(
+            self,
+            PowerUpKind::ShrinkPaddle
+                | PowerUpKind::ReverseControls
+                | PowerUpKind::SpeedUp
+                | PowerUpKind::SingleBall
+        )
+    }
+
+    /// White icon, tinted with `color`. Curses have a frame around theirs.
+    pub fn sprite(self) -> &'static str {
+        match self {
+            PowerUpKind::MultiBall => "sprites/powerups/multi_ball.png",
+            PowerUpKind::SplitBall => "sprites/powerups/split_ball.png",
+            PowerUpKind::BonusPoints => "sprites/powerups/bonus_points.png",
+            PowerUpKind::SlowBall => "sprites/powerups/slow_ball.png",
+            PowerUpKind::ExpandPaddle => "sprites/powerups/expand_paddle.png",
+            PowerUpKind::ShrinkPaddle => "sprites/powerups/shrink_paddle.png",
+            PowerUpKind::Laser => "sprites/powerups/laser.png",
+            PowerUpKind::Catch => "sprites/powerups/catch.png",
+            PowerUpKind::Fireball => "sprites/powerups/fireball.png",
+            PowerUpKind::SafetyNet => "sprites/powerups/safety_net.png",
+            PowerUpKind::FastBall => "sprites/powerups/fast_ball.png",
+            PowerUpKind::Magnet => "sprites/powerups/magnet.png",
+            PowerUpKind::ReverseControls => "sprites/powerups/reverse_controls.png",
+            PowerUpKind::SpeedUp => "sprites/powerups/speed_up.png",
+            PowerUpKind::SingleBall => "sprites/powerups/single_ball.png",
+        }
+    }
+
+    /// Curses all come in dark reds so they stand out from the bright
+    /// pickups at a glance.
+    pub fn color(self) -> Color {
+        match self {
+            PowerUpKind::MultiBall => Color::rgb(0.4, 0.7, 1.0),
+            PowerUpKind::SplitBall => Color::rgb(0.3, 1.0, 0.9),
+            PowerUpKind::BonusPoints => Color::rgb(1.0, 0.85, 0.2),
+            PowerUpKind::SlowBall => Color::rgb(0.6, 0.6, 1.0),
+            PowerUpKind::ExpandPaddle => Color::rgb(0.4, 1.0, 0.4),
+            PowerUpKind::ShrinkPaddle => Color::rgb(0.6, 0.05, 0.1),
+            PowerUpKind::Laser => Color::rgb(1.0, 0.2, 0.6),
+            PowerUpKind::Catch => Color::rgb(0.9, 0.5, 1.0),
+            PowerUpKind::Fireball => Color::rgb(1.0, 0.5, 0.1),
+            PowerUpKind::SafetyNet => Color::rgb(0.3, 0.9, 0.5),
+            PowerUpKind::FastBall => Color::rgb(1.0, 0.9, 0.5),
+            PowerUpKind::Magnet => Color::rgb(0.7, 0.7, 0.75),
+            PowerUpKind::ReverseControls => Color::rgb(0.45, 0.05, 0.3),
+            PowerUpKind::SpeedUp => Color::rgb(0.7, 0.2, 0.0),
+            PowerUpKind::SingleBall => Color::rgb(0.35, 0.0, 0.0),
+        }
+    }
+
+    /// Timed effect started when the power-up is collected, if any.
+    pub fn effect(self) -> Option<EffectKind> {
+        match self {
+            PowerUpKind::SlowBall => Some(EffectKind::SlowBall),
+            PowerUpKind::ExpandPaddle => Some(EffectKind::ExpandPaddle),
+            PowerUpKind::ShrinkPaddle => Some(EffectKind::ShrinkPaddle),
+            PowerUpKind::Laser => Some(EffectKind::Laser),
+            PowerUpKind::Catch => Some(EffectKind::Catch),
+            PowerUpKind::Fireball => Some(EffectKind::Fireball),
+            PowerUpKind::FastBall => Some(EffectKind::FastBall),
+            PowerUpKind::Magnet => Some(EffectKind::Magnet),
+            PowerUpKind::ReverseControls => Some(EffectKind::ReverseControls),
+            _ => None,
+        }
+    }
+}
+
+/// Ball held by the paddle while the catch effect is active. `offset` is
+/// the horizontal distance from the paddle centre where it landed.
+#[derive(Component)]
+pub struct StuckBall {
+    pub offset: f32,
+    pub timer: Timer,
+}
+
+/// Safety net along the bottom edge. Each ball it saves uses up one of
+/// `saves`, and it breaks when none are left.
+#[derive(Component)]
+pub struct Barrier {
+    pub saves: u32,
 }
 
+/// One remaining save of the barrier, drawn as a child of it.
 #[derive(Component)]
-pub struct Paddle{}
+pub struct BarrierPip;
+
+/// Translation at the last two fixed steps. The simulation moves entities in
+/// fixed steps; in between, the rendered translation is blended from these.
+#[derive(Component)]
+pub struct Interpolated {
+    pub previous: Vec3,
+    pub current: Vec3,
+}
+
+impl Interpolated {
+    pub fn new(translation: Vec3) -> Self {
+        Interpolated {
+            previous: translation,
+            current: translation,
+        }
+    }
+}
+
+/// Shot fired straight up by the paddle while the laser is active.
+#[derive(Component)]
+pub struct Projectile;
 
 #[derive(Component)]
 pub struct StartUI;
 
 #[derive(Component)]
 pub struct GameOverUI;
+
+#[derive(Component)]
+pub struct LevelClearedUI;
+
+#[derive(Component)]
+pub struct VictoryUI;
+
+#[derive(Component)]
+pub struct LevelErrorUI;
+
+#[derive(Component)]
+pub struct EditorUI;
+
+#[derive(Component)]
+pub struct EditorBrick;
+
+/// Short flash played on a brick that was hit but survived. Every brick
+/// carries one from the start, finished until the first hit: inserting and
+/// removing it on hits would move bricks between tables, and with it the
+/// order the fixed step walks them in, depending on the frame rate.
+#[derive(Component)]
+pub struct HitFlash {
+    pub timer: Timer,
+}
+
+/// Index of the brick in `BrickLayout::cells`.
+#[derive(Component)]
+pub struct BrickCell {
+    pub index: usize,
+}
+
+/// Translucent placeholder shown while a regenerating brick rebuilds. It has
+/// no `Brick` component, so balls pass straight through it.
+#[derive(Component)]
+pub struct Rebuilding {
+    pub cell: usize,
+    pub timer: Timer,
+}
+
+/// Path followed by a moving brick, as an offset from its grid cell.
+/// `period` is the time in seconds for one full cycle.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum MotionPattern {
+    Horizontal { amplitude: f32, period: f32 },
+    Vertical { amplitude: f32, period: f32 },
+    Circular { radius: f32, period: f32 },
+}
+
+impl MotionPattern {
+    pub fn offset(self, time: f32) -> Vec2 {
+        let phase = |period: f32| time / period.max(0.01) * std::f32::consts::TAU;
+        match self {
+            MotionPattern::Horizontal { amplitude, period } => {
+                Vec2::new(amplitude * phase(period).sin(), 0.0)
+            }
+            MotionPattern::Vertical { amplitude, period } => {
+                Vec2::new(0.0, amplitude * phase(period).sin())
+            }
+            MotionPattern::Circular { radius, period } => {
+                Vec2::new(phase(period).cos(), phase(period).sin()) * radius
+            }
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct BrickMotion {
+    pub pattern: MotionPattern,
+    pub origin: Vec2,
+    pub elapsed: f32,
+    /// Velocity over the last frame, used by the collision response.
+    pub velocity: Vec2,
+}
+
+/// Level file description of a portal. Balls entering it come out of the
+/// other portal with the same `pair`, turned by `rotation` degrees.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct PortalDef {
+    pub pair: u32,
+    #[serde(default)]
+    pub rotation: f32,
+}
+
+#[derive(Component)]
+pub struct Portal {
+    pub pair: u32,
+    pub rotation: f32,
+}
+
+/// Stops a ball that just came out of a portal from going straight back in.
+#[derive(Component)]
+pub struct PortalCooldown {
+    pub timer: Timer,
+}
+
+/// Blast left behind by an explosive brick. Damages nearby bricks when the
+/// fuse runs out, which lets chain reactions spread over several frames.
+#[derive(Component)]
+pub struct Explosion {
+    pub fuse: Timer,
+}
@@ -0,0 +1,160 @@
+use bevy::prelude::*;
+
+// ============================================================================
+// TIMED EFFECTS
+// ============================================================================
+
+/// A power-up effect that lasts for a while.
+///
+/// Effects never change game state directly. Systems ask `ActiveEffects`
+/// what is active every frame and derive their values from it, so expiry
+/// reverts everything without any undo code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EffectKind {
+    SlowBall,
+    FastBall,
+    ExpandPaddle,
+    ShrinkPaddle,
+    /// The paddle shoots when the fire key is held.
+    Laser,
+    /// Balls stick to the paddle until released.
+    Catch,
+    /// Balls go straight through destructible bricks.
+    Fireball,
+    /// Falling pickups drift towards the paddle.
+    Magnet,
+    /// Left and right paddle controls are swapped.
+    ReverseControls,
+}
+
+/// What picking up an effect that is already active does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stacking {
+    /// Restart the timer.
+    Refresh,
+    /// Add the full duration to the time left.
+    Extend,
+    /// Add a stack (up to `max_stacks`) and restart the timer.
+    Stack,
+}
+
+impl EffectKind {
+    /// Duration in seconds.
+    pub fn duration(self) -> f32 {
+        match self {
+            EffectKind::SlowBall => 10.0,
+            EffectKind::FastBall => 10.0,
+            EffectKind::ExpandPaddle => 15.0,
+            EffectKind::ShrinkPaddle => 10.0,
+            EffectKind::Laser => 12.0,
+            EffectKind::Catch => 15.0,
+            EffectKind::Fireball => 8.0,
+            EffectKind::Magnet => 20.0,
+            EffectKind::ReverseControls => 8.0,
+        }
+    }
+
+    pub fn stacking(self) -> Stacking {
+        match self {
+            EffectKind::SlowBall => Stacking::Stack,
+            EffectKind::FastBall => Stacking::Stack,
+            EffectKind::ExpandPaddle => Stacking::Stack,
+            EffectKind::ShrinkPaddle => Stacking::Refresh,
+            EffectKind::Laser => Stacking::Extend,
+            EffectKind::Catch => Stacking::Refresh,
+            EffectKind::Fireball => Stacking::Extend,
+            EffectKind::Magnet => Stacking::Extend,
+            EffectKind::ReverseControls => Stacking::Refresh,
+        }
+    }
+
+    pub fn max_stacks(self) -> u32 {
+        match self {
+            EffectKind::SlowBall => 2,
+            EffectKind::FastBall => 2,
+            EffectKind::ExpandPaddle => 2,
+            EffectKind::ShrinkPaddle => 1,
+            EffectKind::Laser => 1,
+            EffectKind::Catch => 1,
+            EffectKind::Fireball => 1,
+            EffectKind::Magnet => 1,
+            EffectKind::ReverseControls => 1,
+        }
+    }
+}
+
+pub struct ActiveEffect {
+    pub kind: EffectKind,
+    pub remaining: f32,
+    pub stacks: u32,
+}
+
+#[derive(Resource, Default)]
+pub struct ActiveEffects {
+    pub effects: Vec<ActiveEffect>,
+}
+
+impl ActiveEffects {
+    pub fn apply(&mut self, kind: EffectKind) {
+        let Some(effect) = self.effects.iter_mut().find(|e| e.kind == kind) else {
+            self.effects.push(ActiveEffect {
+                kind,
+                remaining: kind.duration(),
+                stacks: 1,
+            });
+            return;
+        };
+
+        match kind.stacking() {
+            Stacking::Refresh => effect.remaining = kind.duration(),
+            Stacking::Extend => effect.remaining += kind.duration(),
+            Stacking::Stack => {
+                effect.stacks = (effect.stacks + 1).min(kind.max_stacks());
+                effect.remaining = kind.duration();
+            }
+        }
+    }
+
+    pub fn is_active(&self, kind: EffectKind) -> bool {
+        self.stacks(kind) > 0
+    }
+
+    /// Number of stacks of `kind`, 0 when it is not active.
+    pub fn stacks(&self, kind: EffectKind) -> u32 {
+        self.effects
+            .iter()
+            .find(|e| e.kind == kind)
+            .map_or(0, |e| e.stacks)
+    }
+
+    /// Counts down every effect and drops the ones that ran out, returning them.
+    pub fn tick(&mut self, delta: f32) -> Vec<EffectKind> {
+        let mut expired = Vec::new();
+        self.effects.retain_mut(|effect| {
+            effect.remaining -= delta;
+            if effect.remaining <= 0.0 {
+                expired.push(effect.kind);
+                false
+            } else {
+                true
+            }
+        });
+        expired
+    }
+
+    pub fn clear(&mut self) {
+        self.effects.clear();
+    }
+
+    /// Multiplier applied to the speed of every ball.
+    pub fn ball_speed_factor(&self) -> f32 {
+        0.7_f32.powi(self.stacks(EffectKind::SlowBall) as i32)
+            * 1.3_f32.powi(self.stacks(EffectKind::FastBall) as i32)
+    }
+
+    /// Multiplier applied to the paddle width.
+    pub fn paddle_width_factor(&self) -> f32 {
+        1.35_f32.powi(self.stacks(EffectKind::ExpandPaddle) as i32)
+            * 0.65_f32.powi(self.stacks(EffectKind::ShrinkPaddle) as i32)
+    }
+}
@@ -1,3 +1,17 @@
+use bevy::prelude::*;
+
 pub struct GameOver {
     pub score: u32,
-}
\ No newline at end of file
+}
+
+pub struct LevelCleared {
+    pub level: usize,
+    pub score: u32,
+}
+
+/// Damage dealt to a brick. Everything that breaks bricks goes through this,
+/// so hp, scoring, drops and explosions are handled in one place.
+pub struct BrickHit {
+    pub brick: Entity,
+    pub damage: u8,
+}
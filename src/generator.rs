@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+
+use crate::components::BrickKind;
+use crate::levels::{BrickDef, DropTable, LevelData, EMPTY_CELL};
+
+// ============================================================================
+// PROCEDURAL LEVELS
+// ============================================================================
+
+pub const GENERATED_COLUMNS: usize = 13;
+pub const GENERATED_ROWS: usize = 9;
+pub const MAX_DIFFICULTY: u32 = 10;
+/// Number of levels in a generated run before the victory screen.
+pub const GENERATED_RUN_LENGTH: usize = 5;
+/// Ball speed at difficulty 1, and how much each difficulty step adds.
+const GENERATED_BALL_SPEED: f32 = 230.0;
+const GENERATED_BALL_SPEED_STEP: f32 = 15.0;
+
+/// SplitMix64. Kept in-tree rather than using `rand`, whose generators are
+/// free to change between versions: a seed has to give the same board forever.
+/// `GameRng` uses it for the same reason.
+pub struct SeededRng(u64);
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        SeededRng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Shape {
+    Rectangle,
+    Diamond,
+    Pyramid,
+    Checker,
+    Stripes,
+    Ring,
+}
+
+const SHAPES: [Shape; 6] = [
+    Shape::Rectangle,
+    Shape::Diamond,
+    Shape::Pyramid,
+    Shape::Checker,
+    Shape::Stripes,
+    Shape::Ring,
+];
+
+impl Shape {
+    /// Whether the cell belongs to the shape. `dx` is the distance in columns
+    /// from the centre column, which keeps every shape symmetric.
+    fn contains(self, row: usize, dx: usize, rows: usize, half_width: usize) -> bool {
+        match self {
+            Shape::Rectangle => true,
+            Shape::Diamond => {
+                let half_height = (rows / 2).max(1);
+                let dy = (2 * row).abs_diff(rows - 1) / 2;
+                dx * half_height + dy * half_width <= half_width * half_height
+            }
+            Shape::Pyramid => dx * (rows - 1).max(1) <= row * half_width,
+            Shape::Checker => (row + dx).is_multiple_of(2),
+            Shape::Stripes => row.is_multiple_of(2),
+            Shape::Ring => {
+                row == 0 || row == rows - 1 || dx == half_width || (row == rows / 2 && dx <= 1)
+            }
+        }
+    }
+}
+
+/// Builds a symmetric brick layout from `seed`. Higher `difficulty` (clamped to
+/// `1..=MAX_DIFFICULTY`) means more rows, fewer gaps, tougher bricks and a
+/// faster ball.
+pub fn generate_level(seed: u64, difficulty: u32) -> LevelData {
+    let difficulty = difficulty.clamp(1, MAX_DIFFICULTY);
+    let toughness = difficulty as f32 / MAX_DIFFICULTY as f32;
+    let mut rng = SeededRng(seed);
+
+    let rows = (5 + difficulty as usize / 2).min(GENERATED_ROWS);
+    let half_width = GENERATED_COLUMNS / 2;
+    let shape = SHAPES[rng.below(SHAPES.len())];
+    let gap_chance = 0.3 - 0.2 * toughness;
+    let max_hp = 1 + (difficulty as u8).div_ceil(3);
+
+    let mut grid = vec![vec![EMPTY_CELL; GENERATED_COLUMNS]; rows];
+    for (row, line) in grid.iter_mut().enumerate() {
+        // Top rows are harder to reach, so they get the tougher bricks
+        let hit_chance = 0.8 * toughness * (1.0 - 0.5 * row as f32 / rows as f32);
+
+        for dx in 0..=half_width {
+            if !shape.contains(row, dx, rows, half_width) || rng.next_f32() < gap_chance {
+                continue;
+            }
+
+            let mut hp = 1;
+            while hp < max_hp && rng.next_f32() < hit_chance {
+                hp += 1;
+            }
+            let cell = char::from(b'0' + hp);
+            line[half_width - dx] = cell;
+            line[half_width + dx] = cell;
+        }
+    }
+
+    // Gaps can eat a sparse shape entirely; never hand out an empty board
+    if grid.iter().flatten().all(|c| *c == EMPTY_CELL) {
+        grid[rows / 2] = vec!['1'; GENERATED_COLUMNS];
+    }
+
+    let legend: BTreeMap<char, BrickDef> = (1..=max_hp)
+        .map(|hp| (char::from(b'0' + hp), BrickDef::new(BrickKind::Normal, hp)))
+        .collect();
+
+    LevelData {
+        name: format!("Generated #{} (difficulty {})", seed, difficulty),
+        spacing: (10.0, 10.0),
+        top_margin: 40.0,
+        ball_speed: Some(GENERATED_BALL_SPEED + GENERATED_BALL_SPEED_STEP * (difficulty - 1) as f32),
+        legend,
+        rows: grid.into_iter().map(|line| line.into_iter().collect()).collect(),
+        row_motion: BTreeMap::new(),
+        drops: DropTable::default(),
+        brick_drops: BTreeMap::new(),
+    }
+}
+
+/// Level `index` of the run started with `run_seed`. Difficulty climbs by one
+/// per level, and the name shows the run seed, the one players share and pass
+/// to `--seed`, rather than the hashed seed of the level.
+pub fn generate_run_level(run_seed: u64, index: usize) -> LevelData {
+    let difficulty = (index as u32 + 1).min(MAX_DIFFICULTY);
+    let mut level = generate_level(level_seed(run_seed, index), difficulty);
+    level.name = format!(
+        "Seed {}, level {} (difficulty {})",
+        run_seed,
+        index + 1,
+        difficulty
+    );
+    level
+}
+
+/// Seed of level `index` of the run started with `seed`. Hashed rather than
+/// added, so the runs of two neighbouring seeds do not share levels.
+pub fn level_seed(seed: u64, index: usize) -> u64 {
+    let mut rng = SeededRng(seed ^ (index as u64).wrapping_mul(0xD6E8_FEB8_6659_FD93));
+    rng.next_u64()
+}
+
+/// Seed passed on the command line as `--seed <n>`, if any.
+pub fn seed_from_args() -> Option<u64> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter()
+        .position(|arg| arg == "--seed")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|seed| seed.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Golden boards: if one of these changes, every shared seed and every
+    // saved regression case changes with it. Only update them on purpose.
+    #[test]
+    fn seeds_give_pinned_levels() {
+        let cases: [(u64, u32, &[&str]); 3] = [
+            (
+                0,
+                1,
+                &[
+                    "......2......",
+                    "...1111111...",
+                    "1111111111111",
+                    "...11.1.11...",
+                    ".............",
+                ],
+            ),
+            (
+                7,
+                3,
+                &[
+                    "1.1.1...1.1.1",
+                    ".1.2.....2.1.",
+                    "1.1.1.1.1.1.1",
+                    ".1.1.....1.1.",
+                    "..1.1...1.1..",
+                    "...1.1.1.1...",
+                ],
+            ),
+            (
+                42,
+                5,
+                &[
+                    ".............",
+                    "....12321....",
+                    "..223111322..",
+                    ".11111311111.",
+                    "..313.1.313..",
+                    "....23132....",
+                    "......1......",
+                ],
+            ),
+        ];
+
+        for (seed, difficulty, rows) in cases {
+            let level = generate_level(seed, difficulty);
+            assert_eq!(level.rows, rows, "seed {} difficulty {}", seed, difficulty);
+        }
+    }
+
+    #[test]
+    fn level_seeds_are_pinned() {
+        assert_eq!(level_seed(0, 0), 16294208416658607535);
+        assert_eq!(level_seed(0, 1), 12085254679833835651);
+        assert_eq!(level_seed(42, 0), 13679457532755275413);
+        assert_eq!(level_seed(42, 4), 14311095118747455958);
+    }
+
+    #[test]
+    fn levels_are_mirror_symmetric() {
+        for seed in 0..50 {
+            let level = generate_level(seed, 1 + seed as u32 % MAX_DIFFICULTY);
+            for row in &level.rows {
+                let reversed: String = row.chars().rev().collect();
+                assert_eq!(*row, reversed, "seed {}", seed);
+            }
+        }
+    }
+
+    #[test]
+    fn neighbouring_seeds_do_not_share_levels() {
+        let run = |seed| -> Vec<u64> {
+            (0..GENERATED_RUN_LENGTH).map(|index| level_seed(seed, index)).collect()
+        };
+        let (a, b) = (run(7), run(8));
+        assert!(a.iter().all(|seed| !b.contains(seed)));
+    }
+}
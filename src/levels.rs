@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy::asset::FileAssetIo;
+use serde::{Deserialize, Serialize};
+
+use crate::components::{BrickKind, MotionPattern, PortalDef, PowerUpKind};
+use crate::generator::SeededRng;
+use crate::resources::Difficulty;
+
+// ============================================================================
+// LEVEL FILES
+// ============================================================================
+
+pub const EMPTY_CELL: char = '.';
+
+/// `assets/levels`, resolved the way the `AssetServer` resolves `assets/`
+/// (next to the binary, or the crate under `cargo run`) rather than against
+/// the working directory, which can be anything.
+pub fn levels_dir() -> PathBuf {
+    FileAssetIo::get_base_path().join("assets").join("levels")
+}
+
+/// Where the editor loads from and saves to. It lives next to the other
+/// levels, so a saved layout becomes part of the normal progression.
+pub fn editor_level_path() -> PathBuf {
+    levels_dir().join("editor.ron")
+}
+
+/// One entry of a level legend: what a character in `rows` turns into.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BrickDef {
+    #[serde(default)]
+    pub kind: BrickKind,
+    #[serde(default = "default_hp")]
+    pub hp: u8,
+    /// Texture override, relative to `assets/`. Picked from `kind` and `hp`
+    /// when missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sprite: Option<String>,
+    /// Makes this brick move. Takes precedence over `LevelData::row_motion`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub motion: Option<MotionPattern>,
+    /// Link group of a regenerating brick. Without one, the bricks of the
+    /// same row are linked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<u32>,
+    /// Turns the cell into a portal instead of a brick; `kind` and `hp` are
+    /// ignored then.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub portal: Option<PortalDef>,
+}
+
+impl BrickDef {
+    pub fn new(kind: BrickKind, hp: u8) -> Self {
+        BrickDef {
+            kind,
+            hp,
+            sprite: None,
+            motion: None,
+            group: None,
+            portal: None,
+        }
+    }
+}
+
+/// A brick layout as stored in `assets/levels/*.ron`.
+///
+/// Every character of `rows` is one grid cell: `.` is empty, anything else is
+/// looked up in `legend`. Row 0 is the top of the board.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct LevelData {
+    #[serde(default)]
+    pub name: String,
+    /// Gap between bricks, horizontally and vertically.
+    #[serde(default = "default_spacing")]
+    pub spacing: (f32, f32),
+    /// Gap between the top edge of the window and the first row.
+    #[serde(default = "default_top_margin")]
+    pub top_margin: f32,
+    /// Speed balls start at. The game default is used when missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ball_speed: Option<f32>,
+    pub legend: BTreeMap<char, BrickDef>,
+    pub rows: Vec<String>,
+    /// Motion shared by every brick of a row, keyed by row index.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub row_motion: BTreeMap<usize, MotionPattern>,
+    /// Power-ups dropped by destroyed bricks.
+    #[serde(default)]
+    pub drops: DropTable,
+    /// Replaces `drops` for the listed brick kinds.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub brick_drops: BTreeMap<BrickKind, DropTable>,
+}
+
+/// Weighted power-up table: a destroyed brick drops something with
+/// probability `chance`, and then picks a kind proportionally to its weight.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DropTable {
+    pub chance: f32,
+    pub weights: Vec<(PowerUpKind, u32)>,
+}
+
+impl Default for DropTable {
+    fn default() -> Self {
+        DropTable {
+            chance: 0.2,
+            weights: vec![
+                (PowerUpKind::MultiBall, 5),
+                (PowerUpKind::SplitBall, 2),
+                (PowerUpKind::BonusPoints, 3),
+                (PowerUpKind::SlowBall, 2),
+                (PowerUpKind::ExpandPaddle, 3),
+                (PowerUpKind::ShrinkPaddle, 1),
+                (PowerUpKind::Laser, 2),
+                (PowerUpKind::Catch, 2),
+                (PowerUpKind::Fireball, 1),
+                (PowerUpKind::SafetyNet, 2),
+                (PowerUpKind::FastBall, 1),
+                (PowerUpKind::Magnet, 2),
+                (PowerUpKind::ReverseControls, 1),
+                (PowerUpKind::SpeedUp, 1),
+                (PowerUpKind::SingleBall, 1),
+            ],
+        }
+    }
+}
+
+impl DropTable {
+    /// Picks a drop, with curse weights scaled for `difficulty`.
+    pub fn roll(&self, rng: &mut SeededRng, difficulty: Difficulty) -> Option<PowerUpKind> {
+        let weight = |kind: PowerUpKind, weight: u32| {
+            if kind.is_curse() {
+                weight * difficulty.curse_weight_percent()
+            } else {
+                weight * 100
+            }
+        };
+
+        let total: u32 = self.weights.iter().map(|(kind, w)| weight(*kind, *w)).sum();
+        if total == 0 || rng.next_f32() >= self.chance {
+            return None;
+        }
+
+        let mut pick = rng.below(total as usize) as u32;
+        for (kind, w) in &self.weights {
+            let w = weight(*kind, *w);
+            if pick < w {
+                return Some(*kind);
+            }
+            pick -= w;
+        }
+        None
+    }
+}
+
+fn default_hp() -> u8 {
+    1
+}
+
+fn default_spacing() -> (f32, f32) {
+    (10.0, 10.0)
+}
+
+fn default_top_margin() -> f32 {
+    10.0
+}
+
+impl LevelData {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let source = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        let level: LevelData = ron::from_str(&source)
+            .map_err(|e| format!("cannot parse {}: {}", path.display(), e))?;
+
+        for (row, line) in level.rows.iter().enumerate() {
+            if let Some(c) = line
+                .chars()
+                .find(|c| *c != EMPTY_CELL && !level.legend.contains_key(c))
+            {
+                return Err(format!(
+                    "{}: row {} uses '{}' which is not in the legend",
+                    path.display(),
+                    row,
+                    c
+                ));
+            }
+        }
+
+        Ok(level)
+    }
+
+    /// An empty `columns` x `rows` grid, used as a starting point in the editor.
+    pub fn blank(columns: usize, rows: usize) -> Self {
+        LevelData {
+            name: "Custom".to_string(),
+            spacing: default_spacing(),
+            top_margin: default_top_margin(),
+            ball_speed: None,
+            legend: BTreeMap::new(),
+            rows: vec![EMPTY_CELL.to_string().repeat(columns); rows],
+            row_motion: BTreeMap::new(),
+            drops: DropTable::default(),
+            brick_drops: BTreeMap::new(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let source = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| format!("cannot serialize level: {}", e))?;
+        fs::write(path, source).map_err(|e| format!("cannot write {}: {}", path.display(), e))
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> Option<char> {
+        self.rows
+            .get(row)
+            .and_then(|line| line.chars().nth(col))
+            .filter(|c| *c != EMPTY_CELL)
+    }
+
+    /// Puts `cell` (a legend key, or `None` to clear) at `row`, `col`, growing
+    /// the grid with empty cells if needed.
+    pub fn set_cell(&mut self, row: usize, col: usize, cell: Option<char>) {
+        let columns = self.columns().max(col + 1);
+        if self.rows.len() <= row {
+            self.rows.resize(row + 1, String::new());
+        }
+
+        let mut chars: Vec<char> = self.rows[row].chars().collect();
+        chars.resize(columns, EMPTY_CELL);
+        chars[col] = cell.unwrap_or(EMPTY_CELL);
+        self.rows[row] = chars.into_iter().collect();
+    }
+
+    /// Width of the grid in cells, i.e. the length of the longest row.
+    pub fn columns(&self) -> usize {
+        self.rows
+            .iter()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn drop_table(&self, kind: BrickKind) -> &DropTable {
+        self.brick_drops.get(&kind).unwrap_or(&self.drops)
+    }
+
+    /// Motion of the brick at `row`, if it moves at all.
+    pub fn motion(&self, row: usize, brick: &BrickDef) -> Option<MotionPattern> {
+        brick.motion.or_else(|| self.row_motion.get(&row).copied())
+    }
+
+    /// Every non-empty cell as `(row, col, brick)`.
+    pub fn cells(&self) -> impl Iterator<Item = (usize, usize, &BrickDef)> + '_ {
+        self.rows.iter().enumerate().flat_map(move |(row, line)| {
+            line.chars()
+                .enumerate()
+                .filter_map(move |(col, c)| self.legend.get(&c).map(|brick| (row, col, brick)))
+        })
+    }
+}
+
+/// All level files in `assets/levels`, in play order (sorted by file name).
+pub fn level_files() -> Vec<PathBuf> {
+    let dir = levels_dir();
+    let mut files: Vec<PathBuf> = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+            .collect(),
+        Err(e) => {
+            eprintln!("Cannot read {}: {}", dir.display(), e);
+            Vec::new()
+        }
+    };
+    files.sort();
+    files
+}
+
+/// Brick types the editor cycles through when a cell is clicked.
+pub fn editor_palette() -> Vec<(char, BrickDef)> {
+    vec![
+        ('G', BrickDef::new(BrickKind::Normal, 1)),
+        ('Y', BrickDef::new(BrickKind::Normal, 2)),
+        ('$', BrickDef::new(BrickKind::Gold, 3)),
+        ('?', BrickDef::new(BrickKind::Invisible, 2)),
+        ('#', BrickDef::new(BrickKind::Steel, 1)),
+        ('*', BrickDef::new(BrickKind::Explosive, 1)),
+        ('R', BrickDef::new(BrickKind::Regenerating, 1)),
+    ]
+}
@@ -1,59 +1,225 @@
+// Bevy systems take their dependencies as arguments and queries spell out
+// their filters in the type, both of which trip these lints by design
+#![allow(clippy::type_complexity, clippy::too_many_arguments)]
+
 use bevy::prelude::*;
 
 mod components;
 mod systems;
 mod resources;
 mod events;
+mod levels;
+mod generator;
+mod effects;
 
 use systems::*;
 use resources::*;
 use events::*;
+use effects::*;
 
 fn main() {
     App::new()
         .add_plugins(DefaultPlugins)
         .add_state::<GameState>()
         .init_resource::<Score>()
+        .init_resource::<CurrentLevel>()
+        .init_resource::<LevelSource>()
+        .init_resource::<ActiveLevel>()
+        .init_resource::<BrickLayout>()
+        .init_resource::<EditorSession>()
+        .init_resource::<ActiveEffects>()
+        .init_resource::<Difficulty>()
+        .init_resource::<GameRng>()
         .add_event::<GameOver>()
+        .add_event::<LevelCleared>()
+        .add_event::<BrickHit>()
+        .insert_resource(FixedTime::new_from_secs(FIXED_TIMESTEP))
+        .edit_schedule(CoreSchedule::FixedUpdate, |schedule| {
+            schedule
+                .configure_set(
+                    SimulationSet.run_if(in_state(GameState::Playing).and_then(level_loaded)),
+                )
+                .configure_sets(
+                    (
+                        GameplaySet::Input,
+                        GameplaySet::Movement,
+                        GameplaySet::Collision,
+                        GameplaySet::Resolution,
+                        GameplaySet::Scoring,
+                        GameplaySet::StateChecks,
+                    )
+                        .chain()
+                        .in_set(SimulationSet),
+                );
+        })
         .add_startup_system(spawn_camera)
 
         // ===== START =====
         .add_system(spawn_start_ui.in_schedule(OnEnter(GameState::Start)))
         .add_system(start_game.run_if(in_state(GameState::Start)))
+        .add_system(open_editor.run_if(in_state(GameState::Start)))
+        .add_system(select_difficulty.run_if(in_state(GameState::Start)))
+
+        // ===== EDITOR =====
+        .add_system(spawn_editor.in_schedule(OnEnter(GameState::Editor)))
+        .add_system(cleanup_editor.in_schedule(OnExit(GameState::Editor)))
+        .add_system(edit_level.run_if(in_state(GameState::Editor)))
+        .add_system(redraw_editor_bricks.after(edit_level).run_if(in_state(GameState::Editor)))
+        .add_system(editor_controls.run_if(in_state(GameState::Editor)))
 
         // ===== PLAYING =====
         .add_system(cleanup_game_entities.in_schedule(OnEnter(GameState::Playing)))
+        .add_system(
+            load_level
+                .before(spawn_bricks)
+                .before(spawn_ball)
+                .in_schedule(OnEnter(GameState::Playing)),
+        )
         .add_systems(
             (spawn_paddle, spawn_ball, spawn_bricks)
                 .in_schedule(OnEnter(GameState::Playing)),
         )
         .add_system(cleanup_game_entities.in_schedule(OnExit(GameState::Playing)))
-        .add_system(paddle_movement.run_if(in_state(GameState::Playing)))
-        .add_system(ball_movement.run_if(in_state(GameState::Playing)))
-        .add_system(confine_paddle.run_if(in_state(GameState::Playing)))
-        .add_system(confine_ball.run_if(in_state(GameState::Playing)))
-        .add_system(update_ball_direction.run_if(in_state(GameState::Playing)))
-        .add_system(ball_brick_collision.run_if(in_state(GameState::Playing)))
-        .add_system(ball_paddle_collision.run_if(in_state(GameState::Playing)))
-        .add_system(power_up_fall.run_if(in_state(GameState::Playing)))
-        .add_system(paddle_collect_power_up.run_if(in_state(GameState::Playing)))
-        .add_system(check_game_over.run_if(in_state(GameState::Playing)))
-        .add_system(update_score.run_if(in_state(GameState::Playing)))
+        .add_system(restore_translations.in_base_set(CoreSet::PreUpdate))
+        .add_system(
+            store_previous_translations
+                .before(SimulationSet)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        )
+        .add_systems(
+            (paddle_movement, fire_laser)
+                .in_set(GameplaySet::Input)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        )
+        .add_systems(
+            (
+                ball_movement,
+                projectile_movement,
+                move_bricks,
+                power_up_fall,
+                resize_paddle,
+                confine_paddle.after(resize_paddle),
+                carry_stuck_balls.after(confine_paddle),
+            )
+                .in_set(GameplaySet::Movement)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        )
+        // Bricks, then the paddle, portals and finally the walls, so the same
+        // contacts always resolve into the same bounce
+        .add_systems(
+            (
+                ball_brick_collision,
+                ball_paddle_collision,
+                ball_portal_collision,
+                confine_ball,
+                update_ball_direction,
+            )
+                .chain()
+                .in_set(GameplaySet::Collision)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        )
+        .add_system(
+            projectile_brick_collision
+                .after(ball_brick_collision)
+                .in_set(GameplaySet::Collision)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        )
+        // Chained as a whole: drops and multi-ball both draw from `GameRng`,
+        // and the draws have to happen in the same order every run
+        .add_systems(
+            (
+                detonate_explosions,
+                resolve_brick_hits,
+                regenerate_bricks,
+                paddle_collect_power_up,
+                tick_portal_cooldowns,
+                tick_effects,
+            )
+                .chain()
+                .in_set(GameplaySet::Resolution)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        )
+        .add_system(
+            update_score
+                .in_set(GameplaySet::Scoring)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        )
+        .add_systems(
+            (check_level_cleared, check_game_over)
+                .chain()
+                .in_set(GameplaySet::StateChecks)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        )
+        .add_system(release_stuck_balls.run_if(in_state(GameState::Playing)))
+        .add_system(interpolate_translations.run_if(in_state(GameState::Playing)))
+        .add_system(animate_bricks.run_if(in_state(GameState::Playing)))
+        .add_system(update_barrier_pips.run_if(in_state(GameState::Playing)))
+        .add_system(update_ball_tint.run_if(in_state(GameState::Playing)))
+        // Clearing the board with the last ball wins: game over stands down
+        // when both happen in the same step, and runs first either way
         .add_system(handle_game_over.run_if(in_state(GameState::Playing)))
+        .add_system(
+            handle_level_cleared
+                .after(handle_game_over)
+                .run_if(in_state(GameState::Playing)),
+        )
+
+        // ===== LEVEL CLEARED =====
+        .add_system(spawn_level_cleared_ui.in_schedule(OnEnter(GameState::LevelCleared)))
+        .add_system(next_level.run_if(in_state(GameState::LevelCleared)))
 
         // ===== GAME OVER =====
         .add_system(spawn_game_over_ui.in_schedule(OnEnter(GameState::GameOver)))
-        .add_system(restart_game.run_if(in_state(GameState::GameOver)))
+
+        // ===== VICTORY =====
+        .add_system(spawn_victory_ui.in_schedule(OnEnter(GameState::Victory)))
+
+        // ===== LEVEL ERROR =====
+        .add_system(spawn_level_error_ui.in_schedule(OnEnter(GameState::LevelError)))
+        .add_system(leave_level_error.run_if(in_state(GameState::LevelError)))
+
+        // ===== GAME OVER / VICTORY =====
+        .add_system(
+            restart_game
+                .run_if(in_state(GameState::GameOver).or_else(in_state(GameState::Victory))),
+        )
 
         // ===== GLOBAL =====
         .add_system(exit_game)
         .run();
 }
 
+/// Gameplay systems, run on the fixed timestep while playing a level that
+/// loaded.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SimulationSet;
+
+/// Stages of one simulation step, run in this order.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GameplaySet {
+    /// Reads the keyboard.
+    Input,
+    /// Moves everything by one step.
+    Movement,
+    /// Finds contacts and bounces balls off them.
+    Collision,
+    /// Applies what the contacts did: brick damage, pickups, timers.
+    Resolution,
+    /// Reports score changes.
+    Scoring,
+    /// Decides whether the level is won or lost.
+    StateChecks,
+}
+
 #[derive(States, Debug, Clone, Eq, PartialEq, Hash, Default)]
 pub enum GameState {
     #[default]
     Start,
+    Editor,
     Playing,
+    LevelCleared,
     GameOver,
+    Victory,
+    /// A level file is missing or broken.
+    LevelError,
 }
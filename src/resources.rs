@@ -1,12 +1,150 @@
 use bevy::prelude::*;
+use rand::prelude::*;
 
-#[derive(Resource)]
+use crate::components::{BrickKind, MotionPattern};
+use crate::generator::{level_seed, SeededRng, GENERATED_RUN_LENGTH};
+use crate::levels::{level_files, BrickDef, LevelData};
+
+#[derive(Resource, Default)]
 pub struct Score {
     pub value: u32,
 }
 
-impl Default for Score {
+/// Index of the level being played within the run described by `LevelSource`.
+#[derive(Resource, Default)]
+pub struct CurrentLevel {
+    pub index: usize,
+}
+
+/// Where the levels of the current run come from.
+#[derive(Resource, Default, Clone, Copy)]
+pub enum LevelSource {
+    /// `assets/levels/*.ron`, in file name order.
+    #[default]
+    Files,
+    /// `generator::generate_run_level`, one seed per run.
+    Generated { seed: u64 },
+}
+
+impl LevelSource {
+    pub fn level_count(&self) -> usize {
+        match self {
+            LevelSource::Files => level_files().len(),
+            LevelSource::Generated { .. } => GENERATED_RUN_LENGTH,
+        }
+    }
+}
+
+/// Layout loaded by `load_level` and built by `spawn_bricks`.
+#[derive(Resource, Default)]
+pub struct ActiveLevel {
+    pub data: LevelData,
+    /// Why the level could not be loaded. The board stays empty and the
+    /// simulation does not run, so a broken level never counts as cleared.
+    pub error: Option<String>,
+}
+
+/// The board as it was built by `spawn_bricks`, one entry per brick, so
+/// regenerating bricks know where and what to rebuild.
+#[derive(Resource, Default)]
+pub struct BrickLayout {
+    pub cells: Vec<LayoutCell>,
+    /// Simulation time since the board was built, in seconds.
+    pub elapsed: f32,
+}
+
+pub struct LayoutCell {
+    pub brick: BrickDef,
+    pub row: usize,
+    pub position: Vec2,
+    pub motion: Option<MotionPattern>,
+    pub state: CellState,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CellState {
+    Alive,
+    /// Destroyed at `at` (in `BrickLayout::elapsed`), waiting to rebuild.
+    Destroyed { at: f32 },
+    Rebuilding,
+    /// Gone for good.
+    Cleared,
+}
+
+impl BrickLayout {
+    /// Whether two regenerating cells belong to the same link group.
+    pub fn linked(&self, a: usize, b: usize) -> bool {
+        let (a, b) = (&self.cells[a], &self.cells[b]);
+        if a.brick.kind != BrickKind::Regenerating || b.brick.kind != BrickKind::Regenerating {
+            return false;
+        }
+        match (a.brick.group, b.brick.group) {
+            (Some(group_a), Some(group_b)) => group_a == group_b,
+            (None, None) => a.row == b.row,
+            _ => false,
+        }
+    }
+
+    /// Whether a destroyed brick is still going to come back.
+    pub fn has_pending(&self) -> bool {
+        self.cells
+            .iter()
+            .any(|cell| matches!(cell.state, CellState::Destroyed { .. } | CellState::Rebuilding))
+    }
+}
+
+/// Randomness used by the simulation: drops, ball launch angles. It is
+/// reseeded at the start of every level, so the same seed and the same
+/// inputs always play out the same way, on any machine and `rand` version.
+#[derive(Resource)]
+pub struct GameRng {
+    pub seed: u64,
+    pub rng: SeededRng,
+}
+
+impl Default for GameRng {
     fn default() -> Self {
-        Score { value: 0 }
+        GameRng::new(random())
     }
-}
\ No newline at end of file
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng {
+            seed,
+            rng: SeededRng::new(seed),
+        }
+    }
+
+    pub fn reseed(&mut self, level: usize) {
+        self.rng = SeededRng::new(level_seed(self.seed, level));
+    }
+}
+
+/// Chosen on the start screen. Only changes how often curses drop.
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// Weight of curses in drop tables, in percent of what the table says.
+    pub fn curse_weight_percent(self) -> u32 {
+        match self {
+            Difficulty::Easy => 40,
+            Difficulty::Normal => 100,
+            Difficulty::Hard => 200,
+        }
+    }
+}
+
+/// Layout being edited in `GameState::Editor`. While `testing` is set,
+/// `load_level` plays this layout instead of the level files.
+#[derive(Resource, Default)]
+pub struct EditorSession {
+    pub level: LevelData,
+    pub testing: bool,
+}
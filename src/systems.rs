@@ -6,26 +6,76 @@ use bevy::app::AppExit;
 use crate::components::*;
 use crate::resources::*;
 use crate::events::*;
+use crate::levels::*;
+use crate::generator::*;
+use crate::effects::*;
 use crate::GameState;
 
 // ============================================================================
 // CONSTANTS
 // ============================================================================
 
+/// Length of one simulation step. Gameplay runs at this rate whatever the
+/// frame rate is, so a seed and a set of inputs always give the same game.
+pub const FIXED_TIMESTEP: f32 = 1.0 / 60.0;
 const PADDLE_SPEED: f32 = 500.0;
+/// Size of the paddle before any expand/shrink effect.
 const PADDLE_SIZE: Vec2 = Vec2::new(120.0, 30.0);
+/// How fast the paddle grows or shrinks towards its target width, in px/s.
+const PADDLE_RESIZE_SPEED: f32 = 200.0;
+/// Angle from vertical the ball leaves the paddle at, from a hit dead in the
+/// centre to a hit on the very edge. Never zero, or the ball could get
+/// stuck bouncing straight up and down.
+const PADDLE_MIN_BOUNCE_ANGLE: f32 = 8.0;
+const PADDLE_MAX_BOUNCE_ANGLE: f32 = 65.0;
+/// Default starting speed, for levels that do not set `ball_speed`.
 const BALL_SPEED: f32 = 250.0;
+/// Limits on the speed a ball actually moves at, power-ups included.
+const MIN_BALL_SPEED: f32 = 120.0;
+const MAX_BALL_SPEED: f32 = 650.0;
+/// Speed gained per second in play, up to `MAX_BALL_SPEED`.
+const BALL_ACCELERATION: f32 = 3.0;
 const BALL_SIZE: Vec2 = Vec2::new(30.0, 30.0);
+/// The ball sprite is round; collisions use this rather than `BALL_SIZE`.
+const BALL_RADIUS: f32 = BALL_SIZE.x / 2.0;
 const BRICK_SIZE: Vec2 = Vec2::new(80.0, 30.0);
-const POWER_UP_DROP_CHANCE: f32 = 0.9;
+/// Angle between the balls of a split, in degrees.
+const SPLIT_BALL_ANGLE: f32 = 20.0;
+const BONUS_POINTS: u32 = 100;
 const POWER_UP_SPEED: f32 = 150.0;
-
-// ============================================================================
-// COMPONENTS
-// ============================================================================
-
-#[derive(Component)]
-pub struct PowerUp {}
+/// Horizontal pull of the magnet, as a fraction of the distance to the
+/// paddle covered per second, and the cap on the resulting speed.
+const MAGNET_STRENGTH: f32 = 3.0;
+const MAGNET_MAX_SPEED: f32 = 400.0;
+/// Pickups further than this from the paddle are left alone.
+const MAGNET_RANGE: f32 = 450.0;
+/// Speed multiplier of the speed-up curse.
+const CURSE_SPEED_UP: f32 = 1.3;
+/// Curse pickups spin, in radians per second, so they are easy to tell apart.
+const CURSE_SPIN: f32 = 4.0;
+const EXPLOSION_FUSE: f32 = 0.15;
+/// A link group destroyed within this many seconds stays destroyed.
+const REGENERATION_WINDOW: f32 = 3.0;
+/// Seconds before a destroyed regenerating brick starts rebuilding. Longer
+/// than the window, so a group always gets its chance first.
+const REGENERATION_DELAY: f32 = 5.0;
+const REBUILD_TIME: f32 = 1.5;
+const HIT_FLASH_TIME: f32 = 0.12;
+const PORTAL_COOLDOWN: f32 = 0.5;
+const LASER_FIRE_INTERVAL: f32 = 0.3;
+const PROJECTILE_SPEED: f32 = 600.0;
+const PROJECTILE_SIZE: Vec2 = Vec2::new(6.0, 20.0);
+/// Seconds a caught ball stays on the paddle before it launches by itself.
+const CATCH_TIMEOUT: f32 = 3.0;
+/// Bounces resolved for one ball in a single frame before giving up.
+const MAX_CONTACTS_PER_FRAME: usize = 4;
+/// Saves given by one safety net pickup, and how many can be banked.
+const BARRIER_SAVES: u32 = 1;
+const MAX_BARRIER_SAVES: u32 = 5;
+const BARRIER_HEIGHT: f32 = 6.0;
+const BARRIER_PIP_SIZE: Vec2 = Vec2::new(10.0, 10.0);
+const EDITOR_COLUMNS: usize = 14;
+const EDITOR_ROWS: usize = 12;
 
 // ============================================================================
 // CAMERA
@@ -54,10 +104,15 @@ pub fn spawn_paddle(
                 -window.height() / 2.0 + 50.0,
                 0.0,
             ),
+            sprite: Sprite {
+                custom_size: Some(PADDLE_SIZE),
+                ..default()
+            },
             texture: assets_server.load("sprites/paddleBlu.png"),
             ..default()
         },
-        Paddle {},
+        Paddle { size: PADDLE_SIZE },
+        Interpolated::new(Vec3::new(0.0, -window.height() / 2.0 + 50.0, 0.0)),
     ));
 }
 
@@ -65,60 +120,253 @@ pub fn spawn_ball(
     mut commands: Commands,
     window_query: Query<&Window, With<PrimaryWindow>>,
     assets_server: Res<AssetServer>,
+    active_level: Res<ActiveLevel>,
+    mut game_rng: ResMut<GameRng>,
 ) {
     let window = window_query.get_single().unwrap();
+    spawn_ball_at(
+        &mut commands,
+        &assets_server,
+        Vec2::new(0.0, -window.height() / 2.0 + 80.0),
+        Vec2::new(game_rng.rng.next_f32(), 1.0).normalize(),
+        level_ball_speed(&active_level),
+    );
+}
+
+fn spawn_ball_at(
+    commands: &mut Commands,
+    assets_server: &AssetServer,
+    position: Vec2,
+    direction: Vec2,
+    speed: f32,
+) {
     commands.spawn((
         SpriteBundle {
-            transform: Transform::from_xyz(
-                0.0,
-                -window.height() / 2.0 + 80.0,
-                0.0,
-            ),
+            transform: Transform::from_xyz(position.x, position.y, 0.0),
             texture: assets_server.load("sprites/ballBlue.png"),
             ..default()
         },
         Ball {
-            direction: Vec2::new(random::<f32>(), 1.0).normalize(),
+            direction,
+            speed,
+            previous: position,
         },
+        Interpolated::new(position.extend(0.0)),
     ));
 }
 
+fn level_ball_speed(active_level: &ActiveLevel) -> f32 {
+    active_level
+        .data
+        .ball_speed
+        .unwrap_or(BALL_SPEED)
+        .clamp(MIN_BALL_SPEED, MAX_BALL_SPEED)
+}
+
+/// Speed the ball actually moves at, with the active effects applied.
+fn effective_speed(ball: &Ball, effects: &ActiveEffects) -> f32 {
+    (ball.speed * effects.ball_speed_factor()).clamp(MIN_BALL_SPEED, MAX_BALL_SPEED)
+}
+
+pub fn load_level(
+    current_level: Res<CurrentLevel>,
+    level_source: Res<LevelSource>,
+    editor_session: Res<EditorSession>,
+    mut active_level: ResMut<ActiveLevel>,
+    mut game_rng: ResMut<GameRng>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    game_rng.reseed(current_level.index);
+    active_level.error = None;
+
+    if editor_session.testing {
+        active_level.data = editor_session.level.clone();
+        return;
+    }
+
+    let loaded = match *level_source {
+        LevelSource::Files => match level_files().get(current_level.index) {
+            Some(path) => LevelData::load(path),
+            None => Err(format!(
+                "no level file for level {} in {}",
+                current_level.index + 1,
+                levels_dir().display()
+            )),
+        },
+        LevelSource::Generated { seed } => Ok(generate_run_level(seed, current_level.index)),
+    };
+
+    match loaded {
+        Ok(level) => {
+            active_level.data = level;
+            println!("Level {}: {}", current_level.index + 1, active_level.data.name);
+        }
+        Err(e) => {
+            eprintln!("Failed to load level: {}", e);
+            active_level.data = LevelData::default();
+            active_level.error = Some(e);
+            next_state.set(GameState::LevelError);
+        }
+    }
+}
+
+/// Run condition of the simulation: nothing moves on a level that failed
+/// to load.
+pub fn level_loaded(active_level: Res<ActiveLevel>) -> bool {
+    active_level.error.is_none()
+}
+
 pub fn spawn_bricks(
     mut commands: Commands,
     assets_server: Res<AssetServer>,
     window_query: Query<&Window, With<PrimaryWindow>>,
+    active_level: Res<ActiveLevel>,
+    mut layout: ResMut<BrickLayout>,
 ) {
     let window = window_query.get_single().unwrap();
+    let level = &active_level.data;
 
-    let rows = 10;
-    let cols = window.width() as usize / (BRICK_SIZE.x as usize + 10);
-
-    let start_x = window.width() / -2.0 + BRICK_SIZE.x / 2.0 + 10.0;
-    let start_y = window.height() / 2.0 - BRICK_SIZE.y / 2.0 - 10.0;
-
-    for row in 0..rows {
-        for col in 0..cols {
-            let hp = if row < 4 { 2 } else { 1 };
-            let texture = if hp == 2 {
-                assets_server.load("sprites/element_yellow_rectangle.png")
-            } else {
-                assets_server.load("sprites/element_green_rectangle.png")
-            };
+    layout.cells.clear();
+    layout.elapsed = 0.0;
+    for (row, col, brick) in level.cells() {
+        let position = cell_position(level, window, row, col);
 
+        if let Some(portal) = brick.portal {
             commands.spawn((
                 SpriteBundle {
-                    transform: Transform::from_xyz(
-                        start_x + col as f32 * (BRICK_SIZE.x + 10.0),
-                        start_y - row as f32 * (BRICK_SIZE.y + 10.0),
-                        0.0,
-                    ),
-                    texture,
+                    sprite: brick_sprite(brick),
+                    transform: Transform::from_xyz(position.x, position.y, 0.0),
+                    texture: brick_texture(&assets_server, brick),
                     ..default()
                 },
-                Brick { hp },
+                Portal {
+                    pair: portal.pair,
+                    rotation: portal.rotation,
+                },
             ));
+            continue;
         }
+
+        let motion = level.motion(row, brick);
+
+        spawn_brick(&mut commands, &assets_server, brick, position, motion, layout.cells.len());
+        layout.cells.push(LayoutCell {
+            brick: brick.clone(),
+            row,
+            position,
+            motion,
+            state: CellState::Alive,
+        });
+    }
+}
+
+fn spawn_brick(
+    commands: &mut Commands,
+    assets_server: &AssetServer,
+    brick: &BrickDef,
+    position: Vec2,
+    motion: Option<MotionPattern>,
+    cell: usize,
+) {
+    let mut brick_entity = commands.spawn((
+        SpriteBundle {
+            sprite: brick_sprite(brick),
+            transform: Transform::from_xyz(position.x, position.y, 0.0),
+            texture: brick_texture(assets_server, brick),
+            visibility: if brick.kind == BrickKind::Invisible {
+                Visibility::Hidden
+            } else {
+                Visibility::Inherited
+            },
+            ..default()
+        },
+        Brick {
+            kind: brick.kind,
+            hp: brick.hp,
+            max_hp: brick.hp,
+        },
+        BrickCell { index: cell },
+        HitFlash {
+            timer: finished_timer(HIT_FLASH_TIME),
+        },
+    ));
+
+    if let Some(pattern) = motion {
+        brick_entity.insert((
+            BrickMotion {
+                pattern,
+                origin: position,
+                elapsed: 0.0,
+                velocity: Vec2::ZERO,
+            },
+            Interpolated::new(position.extend(0.0)),
+        ));
+    }
+}
+
+fn finished_timer(seconds: f32) -> Timer {
+    let mut timer = Timer::from_seconds(seconds, TimerMode::Once);
+    timer.tick(timer.duration());
+    timer
+}
+
+fn brick_texture(assets_server: &AssetServer, brick: &BrickDef) -> Handle<Image> {
+    match &brick.sprite {
+        Some(sprite) => assets_server.load(sprite.as_str()),
+        None if brick.portal.is_some() => assets_server.load("sprites/tileGrey_11.png"),
+        None => assets_server.load(brick.kind.default_sprite(brick.hp)),
+    }
+}
+
+fn brick_sprite(brick: &BrickDef) -> Sprite {
+    if let Some(portal) = brick.portal {
+        // One hue per pair so linked portals are easy to match up
+        return Sprite {
+            color: Color::hsla((portal.pair as f32 * 70.0 + 270.0) % 360.0, 0.8, 0.6, 0.8),
+            custom_size: Some(BRICK_SIZE),
+            ..default()
+        };
+    }
+
+    Sprite {
+        color: brick.kind.color(),
+        // The steel tile is square, stretch it over the whole cell
+        custom_size: (brick.kind == BrickKind::Steel).then_some(BRICK_SIZE),
+        ..default()
+    }
+}
+
+/// Centre of a grid cell, with the grid centred horizontally under the top
+/// edge of the window.
+fn cell_position(level: &LevelData, window: &Window, row: usize, col: usize) -> Vec2 {
+    let (spacing_x, spacing_y) = level.spacing;
+    let grid_width = level.columns() as f32 * (BRICK_SIZE.x + spacing_x) - spacing_x;
+
+    let start_x = -grid_width / 2.0 + BRICK_SIZE.x / 2.0;
+    let start_y = window.height() / 2.0 - BRICK_SIZE.y / 2.0 - level.top_margin;
+
+    Vec2::new(
+        start_x + col as f32 * (BRICK_SIZE.x + spacing_x),
+        start_y - row as f32 * (BRICK_SIZE.y + spacing_y),
+    )
+}
+
+/// Inverse of `cell_position`: the grid cell under a world position, if any.
+fn cell_at(level: &LevelData, window: &Window, position: Vec2) -> Option<(usize, usize)> {
+    let (spacing_x, spacing_y) = level.spacing;
+    let origin = cell_position(level, window, 0, 0);
+
+    let col = ((position.x - origin.x) / (BRICK_SIZE.x + spacing_x) + 0.5).floor();
+    let row = ((origin.y - position.y) / (BRICK_SIZE.y + spacing_y) + 0.5).floor();
+
+    if col < 0.0 || row < 0.0 {
+        return None;
     }
+    let (row, col) = (row as usize, col as usize);
+    if row >= level.rows.len() || col >= level.columns() {
+        return None;
+    }
+    Some((row, col))
 }
 
 pub fn spawn_start_ui(mut commands: Commands, window_query: Query<&Window, With<PrimaryWindow>>) {
@@ -126,6 +374,10 @@ pub fn spawn_start_ui(mut commands: Commands, window_query: Query<&Window, With<
     
     println!("=== BRICK BREAKER ===");
     println!("PRESS SPACE TO START");
+    println!("PRESS G TO PLAY GENERATED LEVELS (--seed <n> TO PICK THE SEED)");
+    println!("PRESS E TO OPEN THE LEVEL EDITOR");
+    println!("PRESS 1 / 2 / 3 FOR EASY / NORMAL / HARD");
+    println!("IN GAME: LEFT / RIGHT TO MOVE, SPACE TO SHOOT, UP TO RELEASE A CAUGHT BALL");
     
     // Background đen
     commands.spawn((
@@ -263,6 +515,199 @@ pub fn spawn_game_over_ui(mut commands: Commands, score: Res<Score>, window_quer
     ));
 }
 
+pub fn spawn_level_cleared_ui(
+    mut commands: Commands,
+    score: Res<Score>,
+    current_level: Res<CurrentLevel>,
+    level_source: Res<LevelSource>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+) {
+    let window = window_query.get_single().unwrap();
+    let level_count = level_source.level_count();
+
+    println!("=== LEVEL {} CLEARED ===", current_level.index + 1);
+    println!("Score: {}", score.value);
+    println!("PRESS SPACE FOR THE NEXT LEVEL");
+
+    // Background đen
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgba(0.0, 0.0, 0.0, 0.95),
+                custom_size: Some(Vec2::new(window.width(), window.height())),
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 0.0, 100.0),
+            ..default()
+        },
+        LevelClearedUI,
+    ));
+
+    // Banner Level Cleared - màu xanh dương
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(0.2, 0.4, 0.8),
+                custom_size: Some(Vec2::new(window.width() * 0.6, 80.0)),
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 150.0, 101.0),
+            ..default()
+        },
+        LevelClearedUI,
+    ));
+
+    // Tiến độ: mỗi level một ô, level đã qua màu xanh lá
+    let pip_size = 30.0;
+    let pip_gap = 10.0;
+    let start_x = -(level_count as f32 * (pip_size + pip_gap) - pip_gap) / 2.0 + pip_size / 2.0;
+    for level in 0..level_count {
+        let color = if level <= current_level.index {
+            Color::rgb(0.2, 0.8, 0.3)
+        } else {
+            Color::rgb(0.4, 0.4, 0.4)
+        };
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::new(pip_size, pip_size)),
+                    ..default()
+                },
+                transform: Transform::from_xyz(
+                    start_x + level as f32 * (pip_size + pip_gap),
+                    30.0,
+                    101.0,
+                ),
+                ..default()
+            },
+            LevelClearedUI,
+        ));
+    }
+
+    // Nút Next - màu xanh lá
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(0.2, 0.8, 0.3),
+                custom_size: Some(Vec2::new(300.0, 70.0)),
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, -100.0, 101.0),
+            ..default()
+        },
+        LevelClearedUI,
+    ));
+}
+
+pub fn spawn_victory_ui(mut commands: Commands, score: Res<Score>, window_query: Query<&Window, With<PrimaryWindow>>) {
+    let window = window_query.get_single().unwrap();
+
+    println!("=== VICTORY ===");
+    println!("All levels cleared!");
+    println!("Final Score: {}", score.value);
+    println!("PRESS R TO PLAY AGAIN");
+    println!("PRESS ESC TO EXIT");
+
+    // Background đen
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgba(0.0, 0.0, 0.0, 0.95),
+                custom_size: Some(Vec2::new(window.width(), window.height())),
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 0.0, 100.0),
+            ..default()
+        },
+        VictoryUI,
+    ));
+
+    // Banner Victory - màu vàng
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(0.9, 0.8, 0.2),
+                custom_size: Some(Vec2::new(window.width() * 0.6, 80.0)),
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 150.0, 101.0),
+            ..default()
+        },
+        VictoryUI,
+    ));
+
+    // Hiển thị điểm - màu trắng
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(0.9, 0.9, 0.9),
+                custom_size: Some(Vec2::new(350.0, 70.0)),
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 30.0, 101.0),
+            ..default()
+        },
+        VictoryUI,
+    ));
+
+    // Nút Play Again - màu xanh lá
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(0.2, 0.8, 0.3),
+                custom_size: Some(Vec2::new(300.0, 70.0)),
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, -100.0, 101.0),
+            ..default()
+        },
+        VictoryUI,
+    ));
+}
+
+pub fn spawn_level_error_ui(
+    mut commands: Commands,
+    active_level: Res<ActiveLevel>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+) {
+    let window = window_query.get_single().unwrap();
+
+    println!("=== LEVEL FAILED TO LOAD ===");
+    if let Some(error) = &active_level.error {
+        println!("{}", error);
+    }
+    println!("PRESS BACKSPACE TO RETURN TO THE MENU");
+
+    // Background đen
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgba(0.0, 0.0, 0.0, 0.95),
+                custom_size: Some(Vec2::new(window.width(), window.height())),
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 0.0, 100.0),
+            ..default()
+        },
+        LevelErrorUI,
+    ));
+
+    // Banner lỗi - màu cam
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(0.9, 0.5, 0.1),
+                custom_size: Some(Vec2::new(window.width() * 0.6, 80.0)),
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 150.0, 101.0),
+            ..default()
+        },
+        LevelErrorUI,
+    ));
+}
+
 // ============================================================================
 // MOVEMENT SYSTEMS
 // ============================================================================
@@ -270,7 +715,8 @@ pub fn spawn_game_over_ui(mut commands: Commands, score: Res<Score>, window_quer
 pub fn paddle_movement(
     keyboard_input: Res<Input<KeyCode>>,
     mut paddle_query: Query<&mut Transform, With<Paddle>>,
-    time: Res<Time>,
+    effects: Res<ActiveEffects>,
+    fixed_time: Res<FixedTime>,
 ) {
     if let Ok(mut transform) = paddle_query.get_single_mut() {
         let mut direction = 0.0;
@@ -281,63 +727,198 @@ pub fn paddle_movement(
         if keyboard_input.pressed(KeyCode::Right) {
             direction += 1.0;
         }
+        if effects.is_active(EffectKind::ReverseControls) {
+            direction = -direction;
+        }
 
-        let movement = direction * PADDLE_SPEED * time.delta_seconds();
+        let movement = direction * PADDLE_SPEED * fixed_time.period.as_secs_f32();
         transform.translation.x += movement;
     }
 }
 
 pub fn ball_movement(
-    mut ball_query: Query<(&mut Transform, &Ball)>,
-    time: Res<Time>,
+    mut ball_query: Query<(&mut Transform, &mut Ball), Without<StuckBall>>,
+    effects: Res<ActiveEffects>,
+    fixed_time: Res<FixedTime>,
 ) {
-    for (mut transform, ball) in ball_query.iter_mut() {
-        let movement = ball.direction * BALL_SPEED * time.delta_seconds();
+    for (mut transform, mut ball) in ball_query.iter_mut() {
+        ball.speed = (ball.speed + BALL_ACCELERATION * fixed_time.period.as_secs_f32()).min(MAX_BALL_SPEED);
+        ball.previous = transform.translation.truncate();
+
+        let movement = ball.direction * effective_speed(&ball, &effects) * fixed_time.period.as_secs_f32();
         transform.translation.x += movement.x;
         transform.translation.y += movement.y;
     }
 }
 
-pub fn power_up_fall(
-    mut query: Query<&mut Transform, With<PowerUp>>,
-    time: Res<Time>,
+pub fn fire_laser(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    paddle_query: Query<(&Transform, &Paddle)>,
+    effects: Res<ActiveEffects>,
+    audio: Res<Audio>,
+    assets_server: Res<AssetServer>,
+    fixed_time: Res<FixedTime>,
+    mut cooldown: Local<f32>,
 ) {
-    for mut transform in query.iter_mut() {
-        transform.translation.y -= POWER_UP_SPEED * time.delta_seconds();
+    *cooldown -= fixed_time.period.as_secs_f32();
+    if !effects.is_active(EffectKind::Laser)
+        || !keyboard_input.pressed(KeyCode::Space)
+        || *cooldown > 0.0
+    {
+        return;
+    }
+    let Ok((paddle_transform, paddle)) = paddle_query.get_single() else {
+        return;
+    };
+    *cooldown = LASER_FIRE_INTERVAL;
+
+    audio.play(assets_server.load("audio/laserLarge_003.ogg"));
+
+    // One shot from each end of the paddle
+    for side in [-1.0, 1.0] {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(1.0, 0.2, 0.3),
+                    custom_size: Some(PROJECTILE_SIZE),
+                    ..default()
+                },
+                transform: Transform::from_xyz(
+                    paddle_transform.translation.x + side * (paddle.size.x / 2.0 - 10.0),
+                    paddle_transform.translation.y + paddle.size.y / 2.0,
+                    0.0,
+                ),
+                ..default()
+            },
+            Projectile,
+            Interpolated::new(Vec3::new(
+                paddle_transform.translation.x + side * (paddle.size.x / 2.0 - 10.0),
+                paddle_transform.translation.y + paddle.size.y / 2.0,
+                0.0,
+            )),
+        ));
     }
 }
 
-// ============================================================================
-// CONFINEMENT SYSTEMS
-// ============================================================================
-
-pub fn confine_paddle(
-    mut paddle_query: Query<&mut Transform, With<Paddle>>,
+pub fn projectile_movement(
+    mut commands: Commands,
+    mut projectile_query: Query<(Entity, &mut Transform), With<Projectile>>,
     window_query: Query<&Window, With<PrimaryWindow>>,
+    fixed_time: Res<FixedTime>,
 ) {
-    if let Ok(mut paddle_transform) = paddle_query.get_single_mut() {
-        let window = window_query.get_single().unwrap();
-
-        let half_paddle_width = PADDLE_SIZE.x / 2.0;
-
-        let x_min = -window.width() / 2.0 + half_paddle_width;
-        let x_max = window.width() / 2.0 - half_paddle_width;
+    let window = window_query.get_single().unwrap();
 
-        let mut translation = paddle_transform.translation;
+    for (entity, mut transform) in projectile_query.iter_mut() {
+        transform.translation.y += PROJECTILE_SPEED * fixed_time.period.as_secs_f32();
 
-        if translation.x < x_min {
-            translation.x = x_min;
-        } else if translation.x > x_max {
-            translation.x = x_max;
+        if transform.translation.y > window.height() / 2.0 {
+            commands.entity(entity).despawn();
         }
-
-        paddle_transform.translation = translation;
     }
 }
 
-pub fn confine_ball(
-    mut ball_query: Query<(Entity, &mut Transform), With<Ball>>,
-    window_query: Query<&Window, With<PrimaryWindow>>,
+pub fn resize_paddle(
+    mut paddle_query: Query<(&mut Paddle, &mut Sprite)>,
+    effects: Res<ActiveEffects>,
+    fixed_time: Res<FixedTime>,
+) {
+    let target_width = PADDLE_SIZE.x * effects.paddle_width_factor();
+    let max_step = PADDLE_RESIZE_SPEED * fixed_time.period.as_secs_f32();
+
+    for (mut paddle, mut sprite) in paddle_query.iter_mut() {
+        let step = (target_width - paddle.size.x).clamp(-max_step, max_step);
+        if step != 0.0 {
+            paddle.size.x += step;
+            sprite.custom_size = Some(paddle.size);
+        }
+    }
+}
+
+pub fn move_bricks(
+    mut brick_query: Query<(&mut Transform, &mut BrickMotion)>,
+    fixed_time: Res<FixedTime>,
+) {
+    let delta = fixed_time.period.as_secs_f32();
+    if delta <= 0.0 {
+        return;
+    }
+
+    for (mut transform, mut motion) in brick_query.iter_mut() {
+        motion.elapsed += delta;
+        let position = motion.origin + motion.pattern.offset(motion.elapsed);
+
+        motion.velocity = (position - transform.translation.truncate()) / delta;
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+}
+
+pub fn power_up_fall(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Transform, &PowerUp), Without<Paddle>>,
+    paddle_query: Query<&Transform, With<Paddle>>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    effects: Res<ActiveEffects>,
+    fixed_time: Res<FixedTime>,
+) {
+    let window = window_query.get_single().unwrap();
+    let magnet = effects.is_active(EffectKind::Magnet);
+
+    for (entity, mut transform, power_up) in query.iter_mut() {
+        transform.translation.y -= POWER_UP_SPEED * fixed_time.period.as_secs_f32();
+        if power_up.kind.is_curse() {
+            transform.rotate_z(CURSE_SPIN * fixed_time.period.as_secs_f32());
+        }
+
+        if let (true, Ok(paddle_transform)) = (magnet, paddle_query.get_single()) {
+            // Pull harder the further away it is: the pickup curves in
+            // and settles over the paddle instead of overshooting
+            let offset = (paddle_transform.translation - transform.translation).truncate();
+            if offset.length() <= MAGNET_RANGE {
+                let drift = (offset.x * MAGNET_STRENGTH).clamp(-MAGNET_MAX_SPEED, MAGNET_MAX_SPEED);
+                transform.translation.x += drift * fixed_time.period.as_secs_f32();
+            }
+        }
+
+        if transform.translation.y < -window.height() / 2.0 {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+// ============================================================================
+// CONFINEMENT SYSTEMS
+// ============================================================================
+
+pub fn confine_paddle(
+    mut paddle_query: Query<(&mut Transform, &Paddle)>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+) {
+    if let Ok((mut paddle_transform, paddle)) = paddle_query.get_single_mut() {
+        let window = window_query.get_single().unwrap();
+
+        let half_paddle_width = paddle.size.x / 2.0;
+
+        let x_min = -window.width() / 2.0 + half_paddle_width;
+        let x_max = window.width() / 2.0 - half_paddle_width;
+
+        let mut translation = paddle_transform.translation;
+
+        if translation.x < x_min {
+            translation.x = x_min;
+        } else if translation.x > x_max {
+            translation.x = x_max;
+        }
+
+        paddle_transform.translation = translation;
+    }
+}
+
+pub fn confine_ball(
+    mut ball_query: Query<(Entity, &mut Transform, &mut Ball)>,
+    mut barrier_query: Query<(Entity, &mut Barrier)>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
     mut commands: Commands,
 ) {
     let window = window_query.get_single().unwrap();
@@ -348,7 +929,7 @@ pub fn confine_ball(
     let y_min = -window.height() / 2.0 + half_ball_size.y;
     let y_max = window.height() / 2.0 - half_ball_size.y;
 
-    for (ball_entity, mut transform) in ball_query.iter_mut() {
+    for (ball_entity, mut transform, mut ball) in ball_query.iter_mut() {
         let mut translation = transform.translation;
 
         if translation.x < x_min {
@@ -357,8 +938,21 @@ pub fn confine_ball(
             translation.x = x_max;
         }
         if translation.y < y_min {
-            commands.entity(ball_entity).despawn();
-            continue;
+            match barrier_query.iter_mut().find(|(_, barrier)| barrier.saves > 0) {
+                Some((barrier_entity, mut barrier)) => {
+                    translation.y = y_min;
+                    ball.direction.y = ball.direction.y.abs();
+
+                    barrier.saves -= 1;
+                    if barrier.saves == 0 {
+                        commands.entity(barrier_entity).despawn_recursive();
+                    }
+                }
+                None => {
+                    commands.entity(ball_entity).despawn();
+                    continue;
+                }
+            }
         } else if translation.y > y_max {
             translation.y = y_max;
         }
@@ -378,14 +972,20 @@ pub fn update_ball_direction(
     let y_min = -window_query.height() / 2.0 + half_ball_size.y;
     let y_max = window_query.height() / 2.0 - half_ball_size.y;
 
+    // Only ever point the ball back into the window: a ball that was already
+    // turned around (by the safety net, or a paddle at the edge) stays put
     for (transform, mut ball) in ball_query.iter_mut() {
         let translation = transform.translation;
 
-        if translation.x <= x_min || translation.x >= x_max {
-            ball.direction.x = -ball.direction.x;
+        if translation.x <= x_min {
+            ball.direction.x = ball.direction.x.abs();
+        } else if translation.x >= x_max {
+            ball.direction.x = -ball.direction.x.abs();
         }
-        if translation.y <= y_min || translation.y >= y_max {
-            ball.direction.y = -ball.direction.y;
+        if translation.y <= y_min {
+            ball.direction.y = ball.direction.y.abs();
+        } else if translation.y >= y_max {
+            ball.direction.y = -ball.direction.y.abs();
         }
     }
 }
@@ -395,123 +995,992 @@ pub fn update_ball_direction(
 // ============================================================================
 
 pub fn ball_paddle_collision(
-    mut ball_query: Query<(&mut Transform, &mut Ball), Without<Paddle>>,
-    paddle_query: Query<&Transform, With<Paddle>>,
+    mut commands: Commands,
+    mut ball_query: Query<(Entity, &mut Transform, &mut Ball), (Without<Paddle>, Without<StuckBall>)>,
+    paddle_query: Query<(&Transform, &Paddle)>,
+    effects: Res<ActiveEffects>,
     audio: Res<Audio>,
     assets_server: Res<AssetServer>,
 ) {
-    let paddle_transform = paddle_query.get_single().unwrap();
+    let (paddle_transform, paddle) = paddle_query.get_single().unwrap();
+    let paddle_position = paddle_transform.translation.truncate();
+    let half_size = paddle.size / 2.0;
 
-    for (mut ball_transform, mut ball) in ball_query.iter_mut() {
-        let distance_x = (ball_transform.translation.x - paddle_transform.translation.x).abs();
-        let distance_y = (ball_transform.translation.y - paddle_transform.translation.y).abs();
+    for (ball_entity, mut ball_transform, mut ball) in ball_query.iter_mut() {
+        if ball.direction.y >= 0.0 {
+            continue;
+        }
 
-        if distance_x <= (BALL_SIZE.x / 2.0 + PADDLE_SIZE.x / 2.0)
-            && distance_y <= (BALL_SIZE.y / 2.0 + PADDLE_SIZE.y / 2.0)
-            && ball.direction.y < 0.0
-        {
-            let sound_effect = assets_server.load("audio/impactPunch_heavy_001.ogg");
-            audio.play(sound_effect);
-            ball.direction.y = -ball.direction.y;
+        let end = ball_transform.translation.truncate();
+        let Some(contact) = sweep_ball(ball.previous, end, paddle_position, half_size) else {
+            continue;
+        };
+
+        let sound_effect = assets_server.load("audio/impactPunch_heavy_001.ogg");
+        audio.play(sound_effect);
+
+        if contact.normal.y > 0.0 {
+            // -1 on the left edge, 1 on the right edge
+            let hit = ((contact.point.x - paddle_position.x) / half_size.x).clamp(-1.0, 1.0);
+            let side = if hit != 0.0 { hit.signum() } else { ball.direction.x.signum() };
+            let angle = (PADDLE_MIN_BOUNCE_ANGLE
+                + (PADDLE_MAX_BOUNCE_ANGLE - PADDLE_MIN_BOUNCE_ANGLE) * hit.abs())
+            .to_radians();
+            ball.direction = Vec2::new(side * angle.sin(), angle.cos());
+        } else if ball.direction.dot(contact.normal) < 0.0 {
+            // Clipped the side: glance off it, the ball is lost anyway. A ball
+            // the paddle ran into while it was already moving away keeps going
+            ball.direction = reflect(ball.direction, contact.normal);
+        }
 
-            let overlap_y = (BALL_SIZE.y / 2.0 + PADDLE_SIZE.y / 2.0) - distance_y;
-            ball_transform.translation.y += overlap_y;
+        let remaining = (end - ball.previous).length() * (1.0 - contact.t);
+        let position = contact.point + ball.direction * remaining;
+        ball_transform.translation.x = position.x;
+        ball_transform.translation.y = position.y;
+        ball.previous = contact.point;
+
+        if effects.is_active(EffectKind::Catch) && contact.normal.y > 0.0 {
+            ball_transform.translation.x = contact.point.x;
+            ball_transform.translation.y = contact.point.y;
+            commands.entity(ball_entity).insert(StuckBall {
+                offset: contact.point.x - paddle_position.x,
+                timer: Timer::from_seconds(CATCH_TIMEOUT, TimerMode::Once),
+            });
         }
     }
 }
 
-pub fn ball_brick_collision(
+pub fn update_ball_tint(
+    mut ball_query: Query<&mut Sprite, With<Ball>>,
+    effects: Res<ActiveEffects>,
+) {
+    let color = if effects.is_active(EffectKind::Fireball) {
+        Color::rgb(1.0, 0.45, 0.1)
+    } else {
+        Color::WHITE
+    };
+
+    for mut sprite in ball_query.iter_mut() {
+        if sprite.color != color {
+            sprite.color = color;
+        }
+    }
+}
+
+pub fn carry_stuck_balls(
+    mut commands: Commands,
+    mut ball_query: Query<(Entity, &mut Transform, &mut Ball, &mut StuckBall), Without<Paddle>>,
+    paddle_query: Query<(&Transform, &Paddle)>,
+    fixed_time: Res<FixedTime>,
+) {
+    let Ok((paddle_transform, paddle)) = paddle_query.get_single() else {
+        return;
+    };
+
+    for (ball_entity, mut ball_transform, mut ball, mut stuck) in ball_query.iter_mut() {
+        // The paddle may have shrunk since the ball landed
+        let half_width = paddle.size.x / 2.0;
+        stuck.offset = stuck.offset.clamp(-half_width, half_width);
+
+        ball_transform.translation.x = paddle_transform.translation.x + stuck.offset;
+        ball_transform.translation.y =
+            paddle_transform.translation.y + (paddle.size.y + BALL_SIZE.y) / 2.0;
+        ball.previous = ball_transform.translation.truncate();
+
+        // The ball already bounced on contact, so it leaves upwards
+        if stuck.timer.tick(fixed_time.period).finished() {
+            commands.entity(ball_entity).remove::<StuckBall>();
+        }
+    }
+}
+
+/// Reads the key press every frame: a fixed step does not run on every
+/// frame, and would miss it. Space is taken by the laser, so that both
+/// can be active without one press doing two things.
+pub fn release_stuck_balls(
     mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    ball_query: Query<Entity, With<StuckBall>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Up) {
+        for ball_entity in ball_query.iter() {
+            commands.entity(ball_entity).remove::<StuckBall>();
+        }
+    }
+}
+
+pub fn ball_brick_collision(
     mut ball_query: Query<(&mut Transform, &mut Ball), Without<Brick>>,
-    mut brick_query: Query<(Entity, &mut Brick, &Transform), Without<Ball>>,
+    brick_query: Query<(Entity, &Brick, &BrickCell, &Transform, Option<&BrickMotion>), Without<Ball>>,
+    mut brick_hit_events: EventWriter<BrickHit>,
+    effects: Res<ActiveEffects>,
+) {
+    let fireball = effects.is_active(EffectKind::Fireball);
+    let half_size = BRICK_SIZE / 2.0;
+
+    for (mut ball_transform, mut ball) in ball_query.iter_mut() {
+        // Bricks already dealt with this frame, so a bounce cannot hit the
+        // same brick again from its contact point
+        let mut done: Vec<Entity> = Vec::new();
+
+        for _ in 0..MAX_CONTACTS_PER_FRAME {
+            let start = ball.previous;
+            let end = ball_transform.translation.truncate();
+
+            let earliest = brick_query
+                .iter()
+                .filter(|(entity, brick, ..)| brick.hp > 0 && !done.contains(entity))
+                .filter_map(|(entity, brick, cell, transform, motion)| {
+                    sweep_ball(start, end, transform.translation.truncate(), half_size)
+                        .map(|contact| (contact, cell.index, entity, brick, motion))
+                })
+                // Ties go to the lowest cell index, whatever order the query has
+                .min_by(|(a, a_cell, ..), (b, b_cell, ..)| a.t.total_cmp(&b.t).then(a_cell.cmp(b_cell)));
+            let Some((contact, _, brick_entity, brick, motion)) = earliest else {
+                break;
+            };
+            done.push(brick_entity);
+
+            if fireball && brick.kind.is_destructible() {
+                // Burn through without bouncing, and keep going
+                brick_hit_events.send(BrickHit {
+                    brick: brick_entity,
+                    damage: brick.hp,
+                });
+                continue;
+            }
+
+            // Work in the brick's frame so a moving brick can catch up with
+            // the ball, or push it along, instead of swallowing it
+            let brick_velocity = motion.map_or(Vec2::ZERO, |m| m.velocity);
+            let speed = effective_speed(&ball, &effects);
+            let relative = ball.direction * speed - brick_velocity;
+
+            // Moving into the brick: bounce and damage it. A ball already
+            // separating from it only needs the push-out below
+            if relative.dot(contact.normal) < 0.0 {
+                ball.direction = (reflect(relative, contact.normal) + brick_velocity).normalize_or_zero();
+                if ball.direction == Vec2::ZERO {
+                    ball.direction = contact.normal;
+                }
+
+                brick_hit_events.send(BrickHit {
+                    brick: brick_entity,
+                    damage: 1,
+                });
+            }
+
+            // Spend the rest of the frame's movement in the new direction
+            let remaining = (end - start).length() * (1.0 - contact.t);
+            let position = contact.point + ball.direction * remaining;
+            ball_transform.translation.x = position.x;
+            ball_transform.translation.y = position.y;
+            ball.previous = contact.point;
+        }
+    }
+}
+
+/// First contact of a ball moving from `start` to `end` with a box.
+struct Contact {
+    /// Fraction of the movement done at the contact, in `0..=1`.
+    t: f32,
+    normal: Vec2,
+    /// Ball centre at the contact.
+    point: Vec2,
+}
+
+/// Sweeps the round ball against the box at `center`. The normal points from
+/// the contact on the box to the ball centre, so a ball clipping a corner
+/// gets a diagonal normal instead of picking a face. A ball that starts
+/// touching, e.g. because the box moved into it, is pushed out.
+fn sweep_ball(start: Vec2, end: Vec2, center: Vec2, half_size: Vec2) -> Option<Contact> {
+    let local = start - center;
+    let mut closest = local.clamp(-half_size, half_size);
+    let offset = local - closest;
+    if offset.length_squared() < BALL_RADIUS * BALL_RADIUS {
+        let normal = if offset != Vec2::ZERO {
+            offset.normalize()
+        } else {
+            // Centre inside the box: out through the nearest face
+            let penetration = half_size - local.abs();
+            let axis = if penetration.x < penetration.y { 0 } else { 1 };
+            let mut normal = Vec2::ZERO;
+            normal[axis] = local[axis].signum();
+            closest[axis] = half_size[axis] * local[axis].signum();
+            normal
+        };
+        return Some(Contact {
+            t: 0.0,
+            normal,
+            point: center + closest + normal * BALL_RADIUS,
+        });
+    }
+
+    // The ball centre hits the box grown by the radius, with rounded corners.
+    // Sweep the square version first, then fix up hits in a corner region.
+    let delta = end - start;
+    let grown = half_size + Vec2::splat(BALL_RADIUS);
+    let entry = if local.x.abs() < grown.x && local.y.abs() < grown.y {
+        // Already inside the square, next to a corner: only the rounded
+        // corner itself can still be hit
+        start
+    } else {
+        let (t, normal) = sweep_slabs(start, delta, center, grown)?;
+        let point = start + delta * t;
+        let local = point - center;
+        if local.x.abs() <= half_size.x || local.y.abs() <= half_size.y {
+            return Some(Contact { t, normal, point });
+        }
+        point
+    };
+
+    let local = entry - center;
+    let corner = center + half_size * Vec2::new(local.x.signum(), local.y.signum());
+    let t = sweep_circle(start, delta, corner, BALL_RADIUS)?;
+    let point = start + delta * t;
+    Some(Contact {
+        t,
+        normal: (point - corner) / BALL_RADIUS,
+        point,
+    })
+}
+
+/// Entry time and face normal of a point moving by `delta` into a box.
+fn sweep_slabs(start: Vec2, delta: Vec2, center: Vec2, half_size: Vec2) -> Option<(f32, Vec2)> {
+    let min = center - half_size;
+    let max = center + half_size;
+    let mut t_enter = 0.0_f32;
+    let mut t_exit = 1.0_f32;
+    let mut normal = Vec2::ZERO;
+
+    for axis in 0..2 {
+        if delta[axis] == 0.0 {
+            if start[axis] <= min[axis] || start[axis] >= max[axis] {
+                return None;
+            }
+            continue;
+        }
+
+        let (near, far) = if delta[axis] > 0.0 {
+            ((min[axis] - start[axis]) / delta[axis], (max[axis] - start[axis]) / delta[axis])
+        } else {
+            ((max[axis] - start[axis]) / delta[axis], (min[axis] - start[axis]) / delta[axis])
+        };
+        if near >= t_enter {
+            t_enter = near;
+            normal = Vec2::ZERO;
+            normal[axis] = -delta[axis].signum();
+        }
+        t_exit = t_exit.min(far);
+    }
+
+    if normal == Vec2::ZERO || t_enter >= t_exit {
+        return None;
+    }
+    Some((t_enter, normal))
+}
+
+/// Entry time of a point moving by `delta` into a circle.
+fn sweep_circle(start: Vec2, delta: Vec2, center: Vec2, radius: f32) -> Option<f32> {
+    let offset = start - center;
+    let a = delta.length_squared();
+    let b = offset.dot(delta);
+    let c = offset.length_squared() - radius * radius;
+    let discriminant = b * b - a * c;
+    if a == 0.0 || discriminant < 0.0 {
+        return None;
+    }
+
+    let t = (-b - discriminant.sqrt()) / a;
+    (0.0..=1.0).contains(&t).then_some(t)
+}
+
+/// Whether the ball at `position` touches the box at `center`.
+fn ball_touches_box(position: Vec2, center: Vec2, half_size: Vec2) -> bool {
+    let local = position - center;
+    local.distance_squared(local.clamp(-half_size, half_size)) < BALL_RADIUS * BALL_RADIUS
+}
+
+/// `direction` bounced off a surface with the given unit normal.
+fn reflect(direction: Vec2, normal: Vec2) -> Vec2 {
+    direction - 2.0 * direction.dot(normal) * normal
+}
+
+pub fn projectile_brick_collision(
+    mut commands: Commands,
+    projectile_query: Query<(Entity, &Transform), With<Projectile>>,
+    brick_query: Query<(Entity, &Brick, &BrickCell, &Transform)>,
+    mut brick_hit_events: EventWriter<BrickHit>,
+) {
+    for (projectile_entity, projectile_transform) in projectile_query.iter() {
+        // A shot can overlap two rows. It flies up, so the lower brick is
+        // the one it reached first; ties go to the lowest cell index
+        let hit = brick_query
+            .iter()
+            .filter(|(_, brick, _, brick_transform)| {
+                let distance =
+                    (projectile_transform.translation - brick_transform.translation).truncate().abs();
+                brick.hp > 0
+                    && distance.x < (PROJECTILE_SIZE.x + BRICK_SIZE.x) / 2.0
+                    && distance.y < (PROJECTILE_SIZE.y + BRICK_SIZE.y) / 2.0
+            })
+            .min_by(|(_, _, a_cell, a), (_, _, b_cell, b)| {
+                a.translation.y.total_cmp(&b.translation.y).then(a_cell.index.cmp(&b_cell.index))
+            });
+
+        if let Some((brick_entity, ..)) = hit {
+            brick_hit_events.send(BrickHit {
+                brick: brick_entity,
+                damage: 1,
+            });
+            commands.entity(projectile_entity).despawn();
+        }
+    }
+}
+
+pub fn ball_portal_collision(
+    mut commands: Commands,
+    mut ball_query: Query<
+        (Entity, &mut Transform, &mut Ball, &mut Interpolated),
+        (Without<Portal>, Without<PortalCooldown>),
+    >,
+    portal_query: Query<(Entity, &Portal, &Transform), Without<Ball>>,
+) {
+    for (ball_entity, mut ball_transform, mut ball, mut interpolated) in ball_query.iter_mut() {
+        let ball_position = ball_transform.translation.truncate();
+
+        let entered = portal_query.iter().find(|(_, _, portal_transform)| {
+            ball_touches_box(ball_position, portal_transform.translation.truncate(), BRICK_SIZE / 2.0)
+        });
+        let Some((entry_entity, entry, _)) = entered else {
+            continue;
+        };
+
+        let exit = portal_query
+            .iter()
+            .find(|(entity, portal, _)| *entity != entry_entity && portal.pair == entry.pair);
+        let Some((_, _, exit_transform)) = exit else {
+            continue;
+        };
+
+        ball_transform.translation.x = exit_transform.translation.x;
+        ball_transform.translation.y = exit_transform.translation.y;
+        ball.previous = exit_transform.translation.truncate();
+        // Jump straight there on screen rather than sliding across the board
+        interpolated.previous = ball_transform.translation;
+        ball.direction = Vec2::from_angle(entry.rotation.to_radians()).rotate(ball.direction);
+
+        commands.entity(ball_entity).insert(PortalCooldown {
+            timer: Timer::from_seconds(PORTAL_COOLDOWN, TimerMode::Once),
+        });
+    }
+}
+
+pub fn tick_portal_cooldowns(
+    mut commands: Commands,
+    mut cooldown_query: Query<(Entity, &mut PortalCooldown)>,
+    fixed_time: Res<FixedTime>,
+) {
+    for (entity, mut cooldown) in cooldown_query.iter_mut() {
+        if cooldown.timer.tick(fixed_time.period).finished() {
+            commands.entity(entity).remove::<PortalCooldown>();
+        }
+    }
+}
+
+pub fn resolve_brick_hits(
+    mut commands: Commands,
+    mut brick_hit_events: EventReader<BrickHit>,
+    mut brick_query: Query<(
+        &mut Brick,
+        &BrickCell,
+        &Transform,
+        &mut Visibility,
+        &mut Handle<Image>,
+        &mut HitFlash,
+    )>,
     mut score: ResMut<Score>,
+    mut layout: ResMut<BrickLayout>,
+    active_level: Res<ActiveLevel>,
+    difficulty: Res<Difficulty>,
+    mut game_rng: ResMut<GameRng>,
     audio: Res<Audio>,
     assets_server: Res<AssetServer>,
 ) {
-    for (mut ball_transform, mut ball) in ball_query.iter_mut() {
-        for (brick_entity, mut brick, brick_transform) in brick_query.iter_mut() {
-            let dx = ball_transform.translation.x - brick_transform.translation.x;
-            let dy = ball_transform.translation.y - brick_transform.translation.y;
-
-            let overlap_x = (BALL_SIZE.x / 2.0 + BRICK_SIZE.x / 2.0) - dx.abs();
-            let overlap_y = (BALL_SIZE.y / 2.0 + BRICK_SIZE.y / 2.0) - dy.abs();
-
-            if overlap_x > 0.0 && overlap_y > 0.0 && brick.hp > 0 {
-                brick.hp -= 1;
-
-                let sound_effect = assets_server.load("audio/impactPunch_medium_004.ogg");
-                let sound_break = assets_server.load("audio/laserLarge_003.ogg");
-
-                if overlap_x < overlap_y {
-                    // va chạm trái / phải
-                    audio.play(sound_effect);
-                    ball.direction.x = -ball.direction.x;
-                    ball_transform.translation.x += overlap_x * dx.signum();
-                } else {
-                    // va chạm trên / dưới
-                    audio.play(sound_effect);
-                    ball.direction.y = -ball.direction.y;
-                    ball_transform.translation.y += overlap_y * dy.signum();
-                }
+    // Settle hits in grid order, not in the order the senders found them,
+    // so every destroyed brick always gets the same drop roll
+    let mut hits: Vec<(usize, &BrickHit)> = brick_hit_events
+        .iter()
+        .filter_map(|event| brick_query.get(event.brick).ok().map(|(_, cell, ..)| (cell.index, event)))
+        .collect();
+    hits.sort_by_key(|(index, _)| *index);
+
+    for (_, event) in hits {
+        let Ok((mut brick, cell, brick_transform, mut visibility, mut texture, mut flash)) =
+            brick_query.get_mut(event.brick)
+        else {
+            continue;
+        };
+        // Already destroyed earlier this frame
+        if brick.hp == 0 {
+            continue;
+        }
 
-                if brick.hp == 0 {
-                    audio.play(sound_break);
-                    commands.entity(brick_entity).despawn();
-                    score.value += 10;
-
-                    if random::<f32>() < POWER_UP_DROP_CHANCE {
-                        commands.spawn((
-                            SpriteBundle {
-                                transform: Transform::from_xyz(
-                                    brick_transform.translation.x,
-                                    brick_transform.translation.y,
-                                    0.0,
-                                ),
-                                texture: assets_server.load("sprites/star.png"),
-                                ..default()
-                            },
-                            PowerUp {},
-                        ));
-                    }
-                }
+        let (sound, settings) = brick.kind.hit_sound();
+        audio.play_with_settings(assets_server.load(sound), settings);
+        if brick.kind == BrickKind::Invisible {
+            *visibility = Visibility::Inherited;
+        }
+        if brick.kind.is_destructible() {
+            brick.hp = brick.hp.saturating_sub(event.damage);
+        }
 
-                break;
+        if brick.hp > 0 {
+            flash.timer.reset();
+            // Follow the hp unless the level picked a texture on purpose
+            if layout.cells[cell.index].brick.sprite.is_none() {
+                *texture = assets_server.load(brick.kind.default_sprite(brick.hp));
+            }
+            continue;
+        }
+
+        audio.play(assets_server.load("audio/laserLarge_003.ogg"));
+        commands.entity(event.brick).despawn();
+        score.value += brick.kind.score();
+
+        layout.cells[cell.index].state = if brick.kind == BrickKind::Regenerating {
+            CellState::Destroyed {
+                at: layout.elapsed,
             }
+        } else {
+            CellState::Cleared
+        };
+
+        if brick.kind == BrickKind::Explosive {
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgba(1.0, 0.5, 0.1, 0.8),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(
+                        brick_transform.translation.x,
+                        brick_transform.translation.y,
+                        1.0,
+                    ),
+                    texture: assets_server.load("sprites/star.png"),
+                    ..default()
+                },
+                Explosion {
+                    fuse: Timer::from_seconds(EXPLOSION_FUSE, TimerMode::Once),
+                },
+            ));
+        }
+
+        if let Some(kind) = active_level.data.drop_table(brick.kind).roll(&mut game_rng.rng, *difficulty) {
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: kind.color(),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(
+                        brick_transform.translation.x,
+                        brick_transform.translation.y,
+                        0.0,
+                    ),
+                    texture: assets_server.load(kind.sprite()),
+                    ..default()
+                },
+                PowerUp { kind },
+                Interpolated::new(brick_transform.translation),
+            ));
         }
     }
 }
 
+pub fn detonate_explosions(
+    mut commands: Commands,
+    mut explosion_query: Query<(Entity, &mut Explosion, &mut Transform)>,
+    brick_query: Query<(Entity, &Transform), (With<Brick>, Without<Explosion>)>,
+    mut brick_hit_events: EventWriter<BrickHit>,
+    active_level: Res<ActiveLevel>,
+    fixed_time: Res<FixedTime>,
+) {
+    // The eight neighbouring cells: one grid step away on each axis, with
+    // half a step of slack for moving bricks, but never two steps
+    let (spacing_x, spacing_y) = active_level.data.spacing;
+    let reach = (BRICK_SIZE + Vec2::new(spacing_x, spacing_y)) * 1.5;
+
+    for (explosion_entity, mut explosion, mut transform) in explosion_query.iter_mut() {
+        explosion.fuse.tick(fixed_time.period);
+        // Blast grows while the fuse burns so the cascade is easy to follow
+        transform.scale = Vec3::splat(1.0 + 3.0 * explosion.fuse.percent());
+
+        if !explosion.fuse.finished() {
+            continue;
+        }
+
+        let center = transform.translation.truncate();
+        for (brick_entity, brick_transform) in brick_query.iter() {
+            let distance = (brick_transform.translation.truncate() - center).abs();
+            if distance.x < reach.x && distance.y < reach.y {
+                brick_hit_events.send(BrickHit {
+                    brick: brick_entity,
+                    damage: 1,
+                });
+            }
+        }
+        commands.entity(explosion_entity).despawn();
+    }
+}
+
 pub fn paddle_collect_power_up(
     mut commands: Commands,
-    paddle_query: Query<&Transform, With<Paddle>>,
-    power_up_query: Query<(Entity, &Transform), With<PowerUp>>,
+    paddle_query: Query<(&Transform, &Paddle)>,
+    power_up_query: Query<(Entity, &Transform, &PowerUp)>,
+    mut ball_query: Query<(Entity, &Transform, &mut Ball)>,
+    mut barrier_query: Query<&mut Barrier>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut score: ResMut<Score>,
+    mut effects: ResMut<ActiveEffects>,
+    active_level: Res<ActiveLevel>,
+    mut game_rng: ResMut<GameRng>,
     asset_server: Res<AssetServer>,
 ) {
-    let paddle_transform = paddle_query.get_single().unwrap();
+    let (paddle_transform, paddle) = paddle_query.get_single().unwrap();
+    // A barrier spawned here only exists once the commands run, so saves
+    // picked up before then are added up and it is spawned once at the end
+    let mut new_barrier_saves = 0;
 
-    for (power_up_entity, power_up_transform) in power_up_query.iter() {
+    for (power_up_entity, power_up_transform, power_up) in power_up_query.iter() {
         let distance_x = (power_up_transform.translation.x - paddle_transform.translation.x).abs();
         let distance_y = (power_up_transform.translation.y - paddle_transform.translation.y).abs();
 
-        if distance_x <= (PADDLE_SIZE.x / 2.0) && distance_y <= (PADDLE_SIZE.y / 2.0) {
+        if distance_x <= (paddle.size.x / 2.0) && distance_y <= (paddle.size.y / 2.0) {
             commands.entity(power_up_entity).despawn();
 
+            if let Some(effect) = power_up.kind.effect() {
+                effects.apply(effect);
+            }
+
+            match power_up.kind {
+                PowerUpKind::MultiBall => {
+                    // Match the balls already in play, so the new one is not
+                    // suddenly slower than everything else
+                    let ball_count = ball_query.iter().count();
+                    let speed = if ball_count == 0 {
+                        level_ball_speed(&active_level)
+                    } else {
+                        ball_query.iter().map(|(_, _, ball)| ball.speed).sum::<f32>() / ball_count as f32
+                    };
+                    spawn_ball_at(
+                        &mut commands,
+                        &asset_server,
+                        paddle_transform.translation.truncate() + Vec2::new(0.0, 40.0),
+                        Vec2::new(game_rng.rng.next_f32(), 1.0).normalize(),
+                        speed,
+                    );
+                }
+                PowerUpKind::SplitBall => {
+                    for (_, ball_transform, ball) in ball_query.iter() {
+                        for angle in [-SPLIT_BALL_ANGLE, SPLIT_BALL_ANGLE] {
+                            spawn_ball_at(
+                                &mut commands,
+                                &asset_server,
+                                ball_transform.translation.truncate(),
+                                Vec2::from_angle(angle.to_radians()).rotate(ball.direction),
+                                ball.speed,
+                            );
+                        }
+                    }
+                }
+                PowerUpKind::BonusPoints => {
+                    score.value += BONUS_POINTS;
+                }
+                PowerUpKind::SpeedUp => {
+                    for (_, _, mut ball) in ball_query.iter_mut() {
+                        ball.speed = (ball.speed * CURSE_SPEED_UP).min(MAX_BALL_SPEED);
+                    }
+                }
+                PowerUpKind::SingleBall => {
+                    // Keep whichever ball is highest up, the one in least danger
+                    let keep = ball_query
+                        .iter()
+                        .max_by(|(_, a, _), (_, b, _)| a.translation.y.total_cmp(&b.translation.y))
+                        .map(|(entity, _, _)| entity);
+                    for (ball_entity, _, _) in ball_query.iter() {
+                        if Some(ball_entity) != keep {
+                            commands.entity(ball_entity).despawn();
+                        }
+                    }
+                }
+                PowerUpKind::SafetyNet => {
+                    if let Some(mut barrier) = barrier_query.iter_mut().next() {
+                        barrier.saves = (barrier.saves + BARRIER_SAVES).min(MAX_BARRIER_SAVES);
+                    } else {
+                        new_barrier_saves += BARRIER_SAVES;
+                    }
+                }
+                // Timed effects, handled above
+                PowerUpKind::SlowBall
+                | PowerUpKind::FastBall
+                | PowerUpKind::ExpandPaddle
+                | PowerUpKind::ShrinkPaddle
+                | PowerUpKind::Laser
+                | PowerUpKind::Catch
+                | PowerUpKind::Fireball
+                | PowerUpKind::Magnet
+                | PowerUpKind::ReverseControls => {}
+            }
+        }
+    }
+
+    if new_barrier_saves > 0 {
+        let window = window_query.get_single().unwrap();
+        spawn_barrier(&mut commands, window, new_barrier_saves.min(MAX_BARRIER_SAVES));
+    }
+}
+
+fn spawn_barrier(commands: &mut Commands, window: &Window, saves: u32) {
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: PowerUpKind::SafetyNet.color(),
+                custom_size: Some(Vec2::new(window.width(), BARRIER_HEIGHT)),
+                ..default()
+            },
+            transform: Transform::from_xyz(
+                0.0,
+                -window.height() / 2.0 + BARRIER_HEIGHT / 2.0,
+                0.0,
+            ),
+            ..default()
+        },
+        Barrier { saves },
+    ));
+}
+
+/// Redraws the pips showing how many saves the barrier has left.
+pub fn update_barrier_pips(
+    mut commands: Commands,
+    barrier_query: Query<(Entity, &Barrier), Changed<Barrier>>,
+) {
+    for (barrier_entity, barrier) in barrier_query.iter() {
+        commands.entity(barrier_entity).despawn_descendants();
+
+        let spacing = BARRIER_PIP_SIZE.x * 2.0;
+        let first = -(barrier.saves as f32 - 1.0) * spacing / 2.0;
+        commands.entity(barrier_entity).with_children(|parent| {
+            for i in 0..barrier.saves {
+                parent.spawn((
+                    SpriteBundle {
+                        sprite: Sprite {
+                            color: Color::WHITE,
+                            custom_size: Some(BARRIER_PIP_SIZE),
+                            ..default()
+                        },
+                        transform: Transform::from_xyz(
+                            first + i as f32 * spacing,
+                            BARRIER_PIP_SIZE.y,
+                            1.0,
+                        ),
+                        ..default()
+                    },
+                    BarrierPip,
+                ));
+            }
+        });
+    }
+}
+
+pub fn tick_effects(mut effects: ResMut<ActiveEffects>, fixed_time: Res<FixedTime>) {
+    for kind in effects.tick(fixed_time.period.as_secs_f32()) {
+        info!("{:?} wore off", kind);
+    }
+}
+
+// ============================================================================
+// BRICK VISUALS
+// ============================================================================
+
+pub fn animate_bricks(
+    mut brick_query: Query<(&Brick, &mut Sprite, &mut Transform, &mut HitFlash)>,
+    time: Res<Time>,
+) {
+    for (brick, mut sprite, mut transform, mut flash) in brick_query.iter_mut() {
+        flash.timer.tick(time.delta());
+        let strength = 1.0 - flash.timer.percent();
+        let color = mix_colors(damaged_color(brick), Color::WHITE, strength);
+        let scale = 1.0 + 0.15 * strength;
+
+        sprite.color = color;
+        transform.scale = Vec3::new(scale, scale, 1.0);
+    }
+}
+
+/// Kind colour, darkened as the brick loses hp.
+fn damaged_color(brick: &Brick) -> Color {
+    let base = brick.kind.color();
+    if brick.max_hp <= 1 {
+        return base;
+    }
+    let health = brick.hp as f32 / brick.max_hp as f32;
+    mix_colors(Color::rgb(0.25, 0.25, 0.25), base, 0.4 + 0.6 * health)
+}
+
+fn mix_colors(from: Color, to: Color, t: f32) -> Color {
+    let [r1, g1, b1, a1] = from.as_rgba_f32();
+    let [r2, g2, b2, a2] = to.as_rgba_f32();
+    Color::rgba(
+        r1 + (r2 - r1) * t,
+        g1 + (g2 - g1) * t,
+        b1 + (b2 - b1) * t,
+        a1 + (a2 - a1) * t,
+    )
+}
+
+// ============================================================================
+// REGENERATION
+// ============================================================================
+
+pub fn regenerate_bricks(
+    mut commands: Commands,
+    mut layout: ResMut<BrickLayout>,
+    mut rebuilding_query: Query<(Entity, &mut Rebuilding, &mut Sprite)>,
+    assets_server: Res<AssetServer>,
+    fixed_time: Res<FixedTime>,
+) {
+    layout.elapsed += fixed_time.period.as_secs_f32();
+    let now = layout.elapsed;
+
+    for index in 0..layout.cells.len() {
+        let CellState::Destroyed { at } = layout.cells[index].state else {
+            continue;
+        };
+
+        // The whole link group went down within the window: gone for good.
+        // A brick with no partners has nothing to race against and always
+        // comes back
+        let group: Vec<usize> = (0..layout.cells.len())
+            .filter(|other| layout.linked(index, *other))
+            .collect();
+        let destroyed_at: Option<Vec<f32>> = group
+            .iter()
+            .map(|i| match layout.cells[*i].state {
+                CellState::Destroyed { at } => Some(at),
+                _ => None,
+            })
+            .collect();
+        if let Some(destroyed_at) = destroyed_at.filter(|_| group.len() > 1) {
+            let first = destroyed_at.iter().copied().fold(f32::INFINITY, f32::min);
+            let last = destroyed_at.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            if last - first <= REGENERATION_WINDOW {
+                for i in group {
+                    layout.cells[i].state = CellState::Cleared;
+                }
+                continue;
+            }
+        }
+
+        if now - at >= REGENERATION_DELAY {
+            let cell = &mut layout.cells[index];
+            cell.state = CellState::Rebuilding;
+
+            let mut sprite = brick_sprite(&cell.brick);
+            sprite.color.set_a(0.2);
             commands.spawn((
                 SpriteBundle {
-                    transform: Transform::from_xyz(
-                        paddle_transform.translation.x,
-                        paddle_transform.translation.y + 40.0,
-                        0.0,
-                    ),
-                    texture: asset_server.load("sprites/ballBlue.png"),
+                    sprite,
+                    transform: Transform::from_xyz(cell.position.x, cell.position.y, 0.0),
+                    texture: brick_texture(&assets_server, &cell.brick),
                     ..default()
                 },
-                Ball {
-                    direction: Vec2::new(random::<f32>(), 1.0).normalize(),
+                Rebuilding {
+                    cell: index,
+                    timer: Timer::from_seconds(REBUILD_TIME, TimerMode::Once),
                 },
             ));
         }
     }
+
+    for (entity, mut rebuilding, mut sprite) in rebuilding_query.iter_mut() {
+        rebuilding.timer.tick(fixed_time.period);
+        sprite.color.set_a(0.2 + 0.6 * rebuilding.timer.percent());
+
+        if rebuilding.timer.finished() {
+            commands.entity(entity).despawn();
+
+            let cell = &mut layout.cells[rebuilding.cell];
+            cell.state = CellState::Alive;
+            spawn_brick(
+                &mut commands,
+                &assets_server,
+                &cell.brick,
+                cell.position,
+                cell.motion,
+                rebuilding.cell,
+            );
+        }
+    }
+}
+
+// ============================================================================
+// LEVEL EDITOR
+// ============================================================================
+
+pub fn spawn_editor(
+    mut commands: Commands,
+    mut editor_session: ResMut<EditorSession>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    assets_server: Res<AssetServer>,
+) {
+    let window = window_query.get_single().unwrap();
+
+    if editor_session.level.rows.is_empty() {
+        editor_session.level = LevelData::load(&editor_level_path())
+            .unwrap_or_else(|_| LevelData::blank(EDITOR_COLUMNS, EDITOR_ROWS));
+    }
+
+    println!("=== LEVEL EDITOR ===");
+    println!("LEFT CLICK: PLACE / CYCLE BRICK");
+    println!("RIGHT CLICK: REMOVE BRICK");
+    println!("PRESS T TO TEST, S TO SAVE, BACKSPACE TO LEAVE");
+
+    // Lưới - mỗi ô một khung mờ
+    let level = &editor_session.level;
+    for row in 0..level.rows.len() {
+        for col in 0..level.columns() {
+            let position = cell_position(level, window, row, col);
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgba(1.0, 1.0, 1.0, 0.08),
+                        custom_size: Some(BRICK_SIZE),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(position.x, position.y, -1.0),
+                    ..default()
+                },
+                EditorUI,
+            ));
+        }
+    }
+
+    spawn_editor_bricks(&mut commands, &assets_server, window, level);
+}
+
+fn spawn_editor_bricks(
+    commands: &mut Commands,
+    assets_server: &AssetServer,
+    window: &Window,
+    level: &LevelData,
+) {
+    for (row, col, brick) in level.cells() {
+        let position = cell_position(level, window, row, col);
+        let mut sprite = brick_sprite(brick);
+        if brick.kind == BrickKind::Invisible {
+            sprite.color.set_a(0.4);
+        }
+
+        commands.spawn((
+            SpriteBundle {
+                sprite,
+                transform: Transform::from_xyz(position.x, position.y, 0.0),
+                texture: brick_texture(assets_server, brick),
+                ..default()
+            },
+            EditorBrick,
+        ));
+    }
+}
+
+pub fn edit_level(
+    mouse_input: Res<Input<MouseButton>>,
+    mut editor_session: ResMut<EditorSession>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+) {
+    let window = window_query.get_single().unwrap();
+
+    let Some(cursor) = window.cursor_position() else {
+        return;
+    };
+    // Cursor is measured from the bottom-left corner, the camera sits at the centre
+    let cursor = cursor - Vec2::new(window.width(), window.height()) / 2.0;
+
+    let Some((row, col)) = cell_at(&editor_session.level, window, cursor) else {
+        return;
+    };
+
+    if mouse_input.just_pressed(MouseButton::Left) {
+        let palette = editor_palette();
+        // Empty cells (and bricks not in the palette) start from the first entry
+        let next = editor_session
+            .level
+            .cell(row, col)
+            .and_then(|c| palette.iter().position(|(key, _)| *key == c))
+            .map_or(0, |index| (index + 1) % palette.len());
+        let (key, brick) = palette[next].clone();
+
+        // Keep what the loaded level already means by this key
+        let level = &mut editor_session.level;
+        level.legend.entry(key).or_insert(brick);
+        level.set_cell(row, col, Some(key));
+    } else if mouse_input.just_pressed(MouseButton::Right) {
+        editor_session.level.set_cell(row, col, None);
+    }
+}
+
+pub fn redraw_editor_bricks(
+    mut commands: Commands,
+    editor_session: Res<EditorSession>,
+    brick_query: Query<Entity, With<EditorBrick>>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    assets_server: Res<AssetServer>,
+) {
+    if !editor_session.is_changed() {
+        return;
+    }
+    let window = window_query.get_single().unwrap();
+
+    for entity in brick_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    spawn_editor_bricks(&mut commands, &assets_server, window, &editor_session.level);
+}
+
+pub fn editor_controls(
+    keyboard: Res<Input<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut editor_session: ResMut<EditorSession>,
+    mut score: ResMut<Score>,
+) {
+    if keyboard.just_pressed(KeyCode::S) {
+        let path = editor_level_path();
+        match editor_session.level.save(&path) {
+            Ok(()) => println!("Level saved to {}", path.display()),
+            Err(e) => eprintln!("Failed to save level: {}", e),
+        }
+    }
+
+    if keyboard.just_pressed(KeyCode::T) {
+        editor_session.testing = true;
+        score.value = 0;
+        next_state.set(GameState::Playing);
+    }
+
+    if keyboard.just_pressed(KeyCode::Back) {
+        next_state.set(GameState::Start);
+    }
+}
+
+pub fn cleanup_editor(
+    mut commands: Commands,
+    editor_query: Query<Entity, Or<(With<EditorUI>, With<EditorBrick>)>>,
+) {
+    for entity in editor_query.iter() {
+        commands.entity(entity).despawn();
+    }
 }
 
 // ============================================================================
@@ -522,13 +1991,62 @@ pub fn start_game(
     keyboard: Res<Input<KeyCode>>,
     mut next_state: ResMut<NextState<GameState>>,
     mut commands: Commands,
+    mut level_source: ResMut<LevelSource>,
+    mut game_rng: ResMut<GameRng>,
+    mut score: ResMut<Score>,
+    mut current_level: ResMut<CurrentLevel>,
     ui_query: Query<Entity, (With<StartUI>, Without<Parent>)>,
 ) {
-    if keyboard.just_pressed(KeyCode::Space) {
+    let generated = keyboard.just_pressed(KeyCode::G);
+    if !generated && !keyboard.just_pressed(KeyCode::Space) {
+        return;
+    }
+
+    let seed = seed_from_args().unwrap_or_else(random);
+    let source = if generated {
+        LevelSource::Generated { seed }
+    } else {
+        LevelSource::Files
+    };
+    println!("Seed: {}", seed);
+    *game_rng = GameRng::new(seed);
+
+    for e in ui_query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+    *level_source = source;
+    // A fresh run, whatever an earlier run or an editor test-play left behind
+    score.value = 0;
+    current_level.index = 0;
+    next_state.set(GameState::Playing);
+}
+
+pub fn select_difficulty(keyboard: Res<Input<KeyCode>>, mut difficulty: ResMut<Difficulty>) {
+    let selected = if keyboard.just_pressed(KeyCode::Key1) {
+        Difficulty::Easy
+    } else if keyboard.just_pressed(KeyCode::Key2) {
+        Difficulty::Normal
+    } else if keyboard.just_pressed(KeyCode::Key3) {
+        Difficulty::Hard
+    } else {
+        return;
+    };
+
+    *difficulty = selected;
+    println!("Difficulty: {:?}", selected);
+}
+
+pub fn open_editor(
+    keyboard: Res<Input<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut commands: Commands,
+    ui_query: Query<Entity, (With<StartUI>, Without<Parent>)>,
+) {
+    if keyboard.just_pressed(KeyCode::E) {
         for e in ui_query.iter() {
             commands.entity(e).despawn_recursive();
         }
-        next_state.set(GameState::Playing);
+        next_state.set(GameState::Editor);
     }
 }
 
@@ -546,11 +2064,79 @@ pub fn check_game_over(
 
 pub fn handle_game_over(
     mut game_over_events: EventReader<GameOver>,
+    level_cleared_events: EventReader<LevelCleared>,
     mut next_state: ResMut<NextState<GameState>>,
+    mut editor_session: ResMut<EditorSession>,
 ) {
+    // The last ball went out as the last brick broke: the level counts as
+    // cleared, and `handle_level_cleared` takes it from here
+    if !level_cleared_events.is_empty() {
+        game_over_events.clear();
+        return;
+    }
+
     for event in game_over_events.iter() {
         println!("Game Over! Your final score is: {}", event.score);
-        next_state.set(GameState::GameOver);
+
+        if editor_session.testing {
+            editor_session.testing = false;
+            next_state.set(GameState::Editor);
+        } else {
+            next_state.set(GameState::GameOver);
+        }
+    }
+}
+
+pub fn check_level_cleared(
+    brick_query: Query<&Brick>,
+    layout: Res<BrickLayout>,
+    score: Res<Score>,
+    current_level: Res<CurrentLevel>,
+    mut level_cleared_events: EventWriter<LevelCleared>,
+) {
+    // Indestructible bricks stay on the board once the level is done
+    if !brick_query.iter().any(|brick| brick.kind.is_destructible()) && !layout.has_pending() {
+        level_cleared_events.send(LevelCleared {
+            level: current_level.index,
+            score: score.value,
+        });
+    }
+}
+
+pub fn handle_level_cleared(
+    mut level_cleared_events: EventReader<LevelCleared>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut editor_session: ResMut<EditorSession>,
+    level_source: Res<LevelSource>,
+) {
+    for event in level_cleared_events.iter() {
+        println!("Level {} cleared! Score: {}", event.level + 1, event.score);
+
+        if editor_session.testing {
+            editor_session.testing = false;
+            next_state.set(GameState::Editor);
+        } else if event.level + 1 < level_source.level_count() {
+            next_state.set(GameState::LevelCleared);
+        } else {
+            next_state.set(GameState::Victory);
+        }
+    }
+}
+
+pub fn next_level(
+    keyboard: Res<Input<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut commands: Commands,
+    mut current_level: ResMut<CurrentLevel>,
+    ui_query: Query<Entity, (With<LevelClearedUI>, Without<Parent>)>,
+) {
+    if keyboard.just_pressed(KeyCode::Space) {
+        for e in ui_query.iter() {
+            commands.entity(e).despawn_recursive();
+        }
+        // Score carries over, paddle and ball are respawned on OnEnter
+        current_level.index += 1;
+        next_state.set(GameState::Playing);
     }
 }
 
@@ -559,20 +2145,36 @@ pub fn restart_game(
     mut next_state: ResMut<NextState<GameState>>,
     mut commands: Commands,
     mut score: ResMut<Score>,
-    ui_query: Query<Entity, (With<GameOverUI>, Without<Parent>)>,
+    mut current_level: ResMut<CurrentLevel>,
+    ui_query: Query<Entity, (Or<(With<GameOverUI>, With<VictoryUI>)>, Without<Parent>)>,
 ) {
     if keyboard.just_pressed(KeyCode::R) {
         // Despawn UI (only root nodes, children will be despawned recursively)
         for e in ui_query.iter() {
             commands.entity(e).despawn_recursive();
         }
-        // Reset score
+        // Reset score and go back to the first level
         score.value = 0;
+        current_level.index = 0;
         // Change state to Playing (this will trigger OnEnter and spawn entities)
         next_state.set(GameState::Playing);
     }
 }
 
+pub fn leave_level_error(
+    keyboard: Res<Input<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut commands: Commands,
+    ui_query: Query<Entity, (With<LevelErrorUI>, Without<Parent>)>,
+) {
+    if keyboard.just_pressed(KeyCode::Back) {
+        for e in ui_query.iter() {
+            commands.entity(e).despawn_recursive();
+        }
+        next_state.set(GameState::Start);
+    }
+}
+
 // ============================================================================
 // CLEANUP SYSTEMS
 // ============================================================================
@@ -583,6 +2185,12 @@ pub fn cleanup_game_entities(
     ball_query: Query<Entity, With<Ball>>,
     brick_query: Query<Entity, With<Brick>>,
     power_up_query: Query<Entity, With<PowerUp>>,
+    explosion_query: Query<Entity, With<Explosion>>,
+    rebuilding_query: Query<Entity, With<Rebuilding>>,
+    portal_query: Query<Entity, With<Portal>>,
+    projectile_query: Query<Entity, With<Projectile>>,
+    barrier_query: Query<Entity, With<Barrier>>,
+    mut effects: ResMut<ActiveEffects>,
 ) {
     for entity in paddle_query.iter() {
         commands.entity(entity).despawn();
@@ -596,6 +2204,56 @@ pub fn cleanup_game_entities(
     for entity in power_up_query.iter() {
         commands.entity(entity).despawn();
     }
+    for entity in explosion_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    for entity in rebuilding_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    for entity in portal_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    for entity in projectile_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    for entity in barrier_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    effects.clear();
+}
+
+// ============================================================================
+// INTERPOLATION
+// ============================================================================
+
+/// Puts back the simulated translation before the fixed steps of the frame,
+/// undoing the blending done for the previous frame.
+pub fn restore_translations(mut query: Query<(&mut Transform, &Interpolated)>) {
+    for (mut transform, interpolated) in query.iter_mut() {
+        transform.translation = interpolated.current;
+    }
+}
+
+/// Runs at the start of every fixed step.
+pub fn store_previous_translations(mut query: Query<(&Transform, &mut Interpolated)>) {
+    for (transform, mut interpolated) in query.iter_mut() {
+        interpolated.previous = transform.translation;
+    }
+}
+
+/// Blends between the last two fixed steps by how far the frame is into
+/// the next one.
+pub fn interpolate_translations(
+    mut query: Query<(&mut Transform, &mut Interpolated)>,
+    fixed_time: Res<FixedTime>,
+) {
+    let alpha = (fixed_time.accumulated().as_secs_f32() / fixed_time.period.as_secs_f32()).min(1.0);
+
+    for (mut transform, mut interpolated) in query.iter_mut() {
+        interpolated.current = transform.translation;
+        transform.translation = interpolated.previous.lerp(interpolated.current, alpha);
+    }
 }
 
 // ============================================================================
@@ -604,7 +2262,7 @@ pub fn cleanup_game_entities(
 
 pub fn update_score(score: Res<Score>) {
     if score.is_changed() {
-        println!("Score: {}", score.value.to_string());
+        println!("Score: {}", score.value);
     }
 }
 
@@ -616,3 +2274,63 @@ pub fn exit_game(
         app_exit_events.send(AppExit);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HALF_SIZE: Vec2 = Vec2::new(40.0, 15.0);
+
+    fn assert_near(a: Vec2, b: Vec2) {
+        assert!(a.distance(b) < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn face_hit_has_axis_normal() {
+        let start = Vec2::new(0.0, 100.0);
+        let end = Vec2::new(0.0, 0.0);
+        let contact = sweep_ball(start, end, Vec2::ZERO, HALF_SIZE).unwrap();
+        assert!((contact.t - 0.7).abs() < 1e-4);
+        assert_near(contact.normal, Vec2::Y);
+        assert_near(contact.point, Vec2::new(0.0, HALF_SIZE.y + BALL_RADIUS));
+    }
+
+    #[test]
+    fn corner_hit_has_diagonal_unit_normal() {
+        let start = Vec2::new(100.0, 75.0);
+        let end = Vec2::new(40.0, 15.0);
+        let contact = sweep_ball(start, end, Vec2::ZERO, HALF_SIZE).unwrap();
+        let diagonal = Vec2::ONE.normalize();
+        assert_near(contact.normal, diagonal);
+        assert!((contact.normal.length() - 1.0).abs() < 1e-4);
+        assert_near(contact.point, HALF_SIZE + diagonal * BALL_RADIUS);
+    }
+
+    #[test]
+    fn start_inside_is_pushed_out() {
+        let start = Vec2::new(0.0, 5.0);
+        let end = Vec2::new(0.0, 0.0);
+        let contact = sweep_ball(start, end, Vec2::ZERO, HALF_SIZE).unwrap();
+        assert_eq!(contact.t, 0.0);
+        assert_near(contact.normal, Vec2::Y);
+        assert_near(contact.point, Vec2::new(0.0, HALF_SIZE.y + BALL_RADIUS));
+    }
+
+    #[test]
+    fn near_miss_past_corner_is_not_a_contact() {
+        // Cuts through the square corner of the grown box, but stays more
+        // than a radius away from the brick's corner
+        let start = Vec2::new(20.0, 60.5);
+        let end = Vec2::new(85.5, -5.0);
+        assert!(sweep_ball(start, end, Vec2::ZERO, HALF_SIZE).is_none());
+    }
+
+    #[test]
+    fn start_next_to_corner_only_hits_the_rounded_corner() {
+        // Inside the grown square, outside the rounded corner, moving in
+        let start = Vec2::new(54.0, 29.0);
+        let contact = sweep_ball(start, HALF_SIZE, Vec2::ZERO, HALF_SIZE).unwrap();
+        assert!(contact.t > 0.0);
+        assert!((contact.point.distance(HALF_SIZE) - BALL_RADIUS).abs() < 1e-3);
+    }
+}